@@ -0,0 +1,13 @@
+/// Generates the C header from `src/lib.rs` into `OUT_DIR`. The checked in
+/// `include/cygnus.h` is compared with it by `tests/ffi.rs`.
+fn main() {
+  println!("cargo:rerun-if-changed=src/lib.rs");
+  println!("cargo:rerun-if-changed=cbindgen.toml");
+
+  let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+  let out_dir = std::env::var("OUT_DIR").unwrap();
+  let config = cbindgen::Config::from_root_or_default(&dir);
+  cbindgen::generate_with_config(&dir, config)
+    .expect("Unable to generate the C header")
+    .write_to_file(std::path::Path::new(&out_dir).join("cygnus.h"));
+}
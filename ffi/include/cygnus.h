@@ -0,0 +1,237 @@
+#ifndef CYGNUS_H
+#define CYGNUS_H
+
+/* Generated by cbindgen from ffi/src/lib.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ State of a running session.
+ */
+typedef enum CygnusState {
+  CYGNUS_STATE_CONNECTING = 0,
+  CYGNUS_STATE_CHALLENGED = 1,
+  CYGNUS_STATE_ONLINE = 2,
+  /*
+   The session failed and will be retried.
+   */
+  CYGNUS_STATE_OFFLINE = 3,
+  /*
+   The session was ended by `cygnus_session_logout`.
+   */
+  CYGNUS_STATE_LOGGED_OUT = 4,
+  /*
+   The session failed and the retries are used up.
+   */
+  CYGNUS_STATE_FAILED = 5,
+} CygnusState;
+
+/*
+ Result of a call.
+ */
+typedef enum CygnusStatus {
+  CYGNUS_STATUS_OK = 0,
+  /*
+   A pointer was `NULL` or a string was not valid UTF-8.
+   */
+  CYGNUS_STATUS_INVALID_ARGUMENT = 1,
+  /*
+   Reading, writing or decrypting a user file failed.
+   */
+  CYGNUS_STATUS_USER = 2,
+  /*
+   Talking to the authentication server failed.
+   */
+  CYGNUS_STATUS_AUTH = 3,
+} CygnusStatus;
+
+/*
+ A session kept online by a background thread.
+ */
+typedef struct CygnusSession CygnusSession;
+
+/*
+ A decrypted user.
+ */
+typedef struct CygnusUser CygnusUser;
+
+/*
+ Settings of a session, start from `cygnus_options_default`.
+ */
+typedef struct CygnusOptions {
+  /*
+   Server as `host:port`, or `NULL` for the default server.
+   */
+  const char *server;
+  uint32_t timeout_ms;
+  uint32_t retry_delay_ms;
+  /*
+   Retries after a failed session, negative to retry forever.
+   */
+  int64_t max_retries;
+} CygnusOptions;
+
+/*
+ Called from the session thread on every state change. It must not call
+ `cygnus_session_logout` or `cygnus_session_free` of its session, which
+ would wait for the thread it runs on and fail instead.
+ */
+typedef void (*CygnusCallback)(enum CygnusState state, void *userdata);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ The message of the last failed call on this thread, or `NULL`. Valid
+ until the next call on the same thread.
+ */
+const char *cygnus_last_error(void);
+
+/*
+ Frees a string returned by this library.
+
+ # Safety
+
+ `s` must be `NULL` or a string returned by this library.
+ */
+void cygnus_string_free(char *s);
+
+/*
+ Creates a user from its fields, `mac` as `xx:xx:xx:xx:xx:xx`.
+
+ # Safety
+
+ The arguments must be `NULL` or NUL-terminated strings.
+ */
+struct CygnusUser *cygnus_user_new(const char *username, const char *password, const char *mac);
+
+/*
+ Reads and decrypts a user file. `identity` is needed for files encrypted
+ to recipients and may be `NULL` otherwise.
+
+ # Safety
+
+ The arguments must be `NULL` or NUL-terminated strings.
+ */
+struct CygnusUser *cygnus_user_read(const char *path, const char *identity);
+
+/*
+ Encrypts a user into a new file readable only by its owner, to
+ `recipient` if it is not `NULL`. An existing file is only replaced when
+ `overwrite` is set.
+
+ # Safety
+
+ `user` must be a live user, the strings `NULL` or NUL-terminated.
+ */
+enum CygnusStatus cygnus_user_write(const struct CygnusUser *user,
+                                    const char *path,
+                                    const char *recipient,
+                                    bool overwrite);
+
+/*
+ The username, to be freed with `cygnus_string_free`.
+
+ # Safety
+
+ `user` must be `NULL` or a live user.
+ */
+char *cygnus_user_username(const struct CygnusUser *user);
+
+/*
+ Copies the 6 bytes of the MAC address to `out`.
+
+ # Safety
+
+ `user` must be a live user and `out` point to 6 writable bytes.
+ */
+enum CygnusStatus cygnus_user_mac(const struct CygnusUser *user, uint8_t *out);
+
+/*
+ Frees a user and wipes its password.
+
+ # Safety
+
+ `user` must be `NULL` or a user that is not used afterwards.
+ */
+void cygnus_user_free(struct CygnusUser *user);
+
+struct CygnusOptions cygnus_options_default(void);
+
+/*
+ Starts keeping `user` online in a background thread. The user is copied
+ and can be freed right away. `callback` may be `NULL`.
+
+ # Safety
+
+ `user` and `options` must be live, `userdata` must be usable from another
+ thread until the session is freed.
+ */
+struct CygnusSession *cygnus_session_start(const struct CygnusUser *user,
+                                           const struct CygnusOptions *options,
+                                           CygnusCallback callback,
+                                           void *userdata);
+
+/*
+ The current state, `CYGNUS_STATE_FAILED` for a `NULL` session.
+
+ # Safety
+
+ `session` must be `NULL` or a live session.
+ */
+enum CygnusState cygnus_session_state(const struct CygnusSession *session);
+
+/*
+ Copies the 4 bytes of the address assigned by the server to `out`,
+ all zero before the first challenge. Does nothing if an argument is
+ `NULL`.
+
+ # Safety
+
+ `session` must be `NULL` or a live session and `out` `NULL` or point to
+ 4 writable bytes.
+ */
+void cygnus_session_client_ip(const struct CygnusSession *session, uint8_t *out);
+
+/*
+ The error that ended the last session attempt, or `NULL`. To be freed
+ with `cygnus_string_free`.
+
+ # Safety
+
+ `session` must be `NULL` or a live session.
+ */
+char *cygnus_session_error(const struct CygnusSession *session);
+
+/*
+ Logs out and stops the session thread, waiting for it to finish.
+ Returns `CYGNUS_STATUS_AUTH` when the server did not confirm the logout,
+ and `CYGNUS_STATUS_OK` if the session was not online. Called from the
+ callback, it does nothing and returns `CYGNUS_STATUS_INVALID_ARGUMENT`.
+
+ # Safety
+
+ `session` must be `NULL` or a live session.
+ */
+enum CygnusStatus cygnus_session_logout(struct CygnusSession *session);
+
+/*
+ Frees a session, logging out first if it is still running. Called from
+ the callback, it does nothing.
+
+ # Safety
+
+ `session` must be `NULL` or a session that is not used afterwards.
+ */
+void cygnus_session_free(struct CygnusSession *session);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CYGNUS_H */
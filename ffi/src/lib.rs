@@ -0,0 +1,557 @@
+//! C interface of cygnus, see `include/cygnus.h`.
+//!
+//! Objects are opaque pointers owned by the caller and released with the
+//! matching `*_free` function. Functions that fail return `NULL` or a
+//! non-zero [`CygnusStatus`], with a message available from
+//! [`cygnus_last_error`] on the same thread.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{JoinHandle, ThreadId};
+use std::time::Duration;
+
+use cygnus::auth::{
+  AuthError, Command, Controller, DrClient, Event, RetryPolicy,
+};
+use cygnus::user::provider::CredentialProvider;
+use cygnus::user::{self, error::UserResult, perms, User};
+
+/// Result of a call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CygnusStatus {
+  Ok = 0,
+  /// A pointer was `NULL` or a string was not valid UTF-8.
+  InvalidArgument = 1,
+  /// Reading, writing or decrypting a user file failed.
+  User = 2,
+  /// Talking to the authentication server failed.
+  Auth = 3,
+}
+
+/// State of a running session.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CygnusState {
+  Connecting = 0,
+  Challenged = 1,
+  Online = 2,
+  /// The session failed and will be retried.
+  Offline = 3,
+  /// The session was ended by `cygnus_session_logout`.
+  LoggedOut = 4,
+  /// The session failed and the retries are used up.
+  Failed = 5,
+}
+
+/// Called from the session thread on every state change. It must not call
+/// `cygnus_session_logout` or `cygnus_session_free` of its session, which
+/// would wait for the thread it runs on and fail instead.
+pub type CygnusCallback =
+  Option<extern "C" fn(state: CygnusState, userdata: *mut c_void)>;
+
+/// Settings of a session, start from `cygnus_options_default`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CygnusOptions {
+  /// Server as `host:port`, or `NULL` for the default server.
+  pub server: *const c_char,
+  pub timeout_ms: u32,
+  pub retry_delay_ms: u32,
+  /// Retries after a failed session, negative to retry forever.
+  pub max_retries: i64,
+}
+
+/// A decrypted user.
+pub struct CygnusUser(User);
+
+/// A session kept online by a background thread.
+pub struct CygnusSession {
+  status: Arc<Mutex<SessionStatus>>,
+  control: Controller,
+  thread: Mutex<Option<JoinHandle<()>>>,
+  thread_id: ThreadId,
+}
+
+impl CygnusSession {
+  /// Whether the caller runs on the session thread, i.e. in the callback.
+  fn in_callback(&self) -> bool {
+    std::thread::current().id() == self.thread_id
+  }
+}
+
+struct SessionStatus {
+  state: CygnusState,
+  client_ip: Ipv4Addr,
+  error: Option<String>,
+  logout_error: Option<String>,
+}
+
+/// Locks `mutex` even if the session thread panicked while holding it, so
+/// the panic does not spread into the caller.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+  mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+struct Userdata(*mut c_void);
+
+// SAFETY: the caller of cygnus_session_start promises userdata may be used
+// from the session thread
+unsafe impl Send for Userdata {}
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error<E: ToString>(error: E) {
+  let message = CString::new(error.to_string().replace('\0', " ")).ok();
+  LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+unsafe fn str_arg<'a>(ptr: *const c_char) -> Option<&'a str> {
+  if ptr.is_null() {
+    set_last_error("NULL argument");
+    return None;
+  }
+  match CStr::from_ptr(ptr).to_str() {
+    Ok(s) => Some(s),
+    Err(e) => {
+      set_last_error(e);
+      None
+    }
+  }
+}
+
+unsafe fn opt_str_arg<'a>(ptr: *const c_char) -> Result<Option<&'a str>, ()> {
+  if ptr.is_null() {
+    return Ok(None);
+  }
+  str_arg(ptr).map(Some).ok_or(())
+}
+
+fn into_c_string(s: &str) -> *mut c_char {
+  CString::new(s).map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// The message of the last failed call on this thread, or `NULL`. Valid
+/// until the next call on the same thread.
+#[no_mangle]
+pub extern "C" fn cygnus_last_error() -> *const c_char {
+  LAST_ERROR.with(|last| {
+    last
+      .borrow()
+      .as_ref()
+      .map_or(std::ptr::null(), |s| s.as_ptr())
+  })
+}
+
+/// Frees a string returned by this library.
+///
+/// # Safety
+///
+/// `s` must be `NULL` or a string returned by this library.
+#[no_mangle]
+pub unsafe extern "C" fn cygnus_string_free(s: *mut c_char) {
+  if !s.is_null() {
+    drop(CString::from_raw(s));
+  }
+}
+
+/// Creates a user from its fields, `mac` as `xx:xx:xx:xx:xx:xx`.
+///
+/// # Safety
+///
+/// The arguments must be `NULL` or NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn cygnus_user_new(
+  username: *const c_char,
+  password: *const c_char,
+  mac: *const c_char,
+) -> *mut CygnusUser {
+  let (Some(username), Some(password), Some(mac)) =
+    (str_arg(username), str_arg(password), str_arg(mac))
+  else {
+    return std::ptr::null_mut();
+  };
+  let user = User::transform_mac(mac)
+    .and_then(|mac| User::new(username.to_string(), password.into(), mac));
+  match user {
+    Ok(user) => Box::into_raw(Box::new(CygnusUser(user))),
+    Err(e) => {
+      set_last_error(e);
+      std::ptr::null_mut()
+    }
+  }
+}
+
+/// Reads and decrypts a user file. `identity` is needed for files encrypted
+/// to recipients and may be `NULL` otherwise.
+///
+/// # Safety
+///
+/// The arguments must be `NULL` or NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn cygnus_user_read(
+  path: *const c_char,
+  identity: *const c_char,
+) -> *mut CygnusUser {
+  let (Some(path), Ok(identity)) = (str_arg(path), opt_str_arg(identity))
+  else {
+    return std::ptr::null_mut();
+  };
+  match user::read_user_file(path, identity) {
+    Ok((_, user)) => Box::into_raw(Box::new(CygnusUser(user))),
+    Err(e) => {
+      set_last_error(e);
+      std::ptr::null_mut()
+    }
+  }
+}
+
+/// Encrypts a user into a new file readable only by its owner, to
+/// `recipient` if it is not `NULL`. An existing file is only replaced when
+/// `overwrite` is set.
+///
+/// # Safety
+///
+/// `user` must be a live user, the strings `NULL` or NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn cygnus_user_write(
+  user: *const CygnusUser,
+  path: *const c_char,
+  recipient: *const c_char,
+  overwrite: bool,
+) -> CygnusStatus {
+  let (Some(user), Some(path), Ok(recipient)) =
+    (user.as_ref(), str_arg(path), opt_str_arg(recipient))
+  else {
+    set_last_error("NULL or invalid argument");
+    return CygnusStatus::InvalidArgument;
+  };
+  let recipients = recipient
+    .map(str::to_string)
+    .into_iter()
+    .collect::<Vec<_>>();
+  let result =
+    user::encrypt_user(user.0.clone(), &recipients).and_then(|buf| {
+      if overwrite {
+        perms::write_private_atomic(path, &buf)
+      } else {
+        Ok(perms::create_private(path)?.write_all(&buf)?)
+      }
+    });
+  match result {
+    Ok(_) => CygnusStatus::Ok,
+    Err(e) => {
+      set_last_error(e);
+      CygnusStatus::User
+    }
+  }
+}
+
+/// The username, to be freed with `cygnus_string_free`.
+///
+/// # Safety
+///
+/// `user` must be `NULL` or a live user.
+#[no_mangle]
+pub unsafe extern "C" fn cygnus_user_username(
+  user: *const CygnusUser,
+) -> *mut c_char {
+  match user.as_ref() {
+    Some(user) => into_c_string(&user.0.username),
+    None => std::ptr::null_mut(),
+  }
+}
+
+/// Copies the 6 bytes of the MAC address to `out`.
+///
+/// # Safety
+///
+/// `user` must be a live user and `out` point to 6 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn cygnus_user_mac(
+  user: *const CygnusUser,
+  out: *mut u8,
+) -> CygnusStatus {
+  match user.as_ref() {
+    Some(user) if !out.is_null() => {
+      std::ptr::copy_nonoverlapping(user.0.mac.as_ptr(), out, 6);
+      CygnusStatus::Ok
+    }
+    _ => CygnusStatus::InvalidArgument,
+  }
+}
+
+/// Frees a user and wipes its password.
+///
+/// # Safety
+///
+/// `user` must be `NULL` or a user that is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn cygnus_user_free(user: *mut CygnusUser) {
+  if !user.is_null() {
+    drop(Box::from_raw(user));
+  }
+}
+
+#[no_mangle]
+pub extern "C" fn cygnus_options_default() -> CygnusOptions {
+  let client = DrClient::default();
+  let retry = client.retry();
+  CygnusOptions {
+    server: std::ptr::null(),
+    timeout_ms: client.timeout().as_millis() as u32,
+    retry_delay_ms: retry.delay.as_millis() as u32,
+    max_retries: -1,
+  }
+}
+
+/// Starts keeping `user` online in a background thread. The user is copied
+/// and can be freed right away. `callback` may be `NULL`.
+///
+/// # Safety
+///
+/// `user` and `options` must be live, `userdata` must be usable from another
+/// thread until the session is freed.
+#[no_mangle]
+pub unsafe extern "C" fn cygnus_session_start(
+  user: *const CygnusUser,
+  options: *const CygnusOptions,
+  callback: CygnusCallback,
+  userdata: *mut c_void,
+) -> *mut CygnusSession {
+  let (Some(user), Some(options)) = (user.as_ref(), options.as_ref()) else {
+    set_last_error("NULL argument");
+    return std::ptr::null_mut();
+  };
+  let Ok(server) = opt_str_arg(options.server) else {
+    return std::ptr::null_mut();
+  };
+  let control = Controller::new();
+  let client = DrClient::builder()
+    .server(server.unwrap_or(DrClient::DEFAULT_SERVER))
+    .timeout(Duration::from_millis(options.timeout_ms.into()))
+    .retry(RetryPolicy {
+      max_retries: u64::try_from(options.max_retries).ok(),
+      delay: Duration::from_millis(options.retry_delay_ms.into()),
+    })
+    .control(control.clone())
+    .build();
+
+  let status = Arc::new(Mutex::new(SessionStatus {
+    state: CygnusState::Connecting,
+    client_ip: Ipv4Addr::UNSPECIFIED,
+    error: None,
+    logout_error: None,
+  }));
+  let worker = Worker {
+    client,
+    user: SessionUser(user.0.clone()),
+    status: status.clone(),
+    callback,
+    userdata: Userdata(userdata),
+  };
+  let thread = std::thread::Builder::new()
+    .name("cygnus-session".to_string())
+    .spawn(move || worker.run());
+  match thread {
+    Ok(thread) => Box::into_raw(Box::new(CygnusSession {
+      status,
+      control,
+      thread_id: thread.thread().id(),
+      thread: Mutex::new(Some(thread)),
+    })),
+    Err(e) => {
+      set_last_error(e);
+      std::ptr::null_mut()
+    }
+  }
+}
+
+/// The current state, `CYGNUS_STATE_FAILED` for a `NULL` session.
+///
+/// # Safety
+///
+/// `session` must be `NULL` or a live session.
+#[no_mangle]
+pub unsafe extern "C" fn cygnus_session_state(
+  session: *const CygnusSession,
+) -> CygnusState {
+  let Some(session) = session.as_ref() else {
+    set_last_error("NULL argument");
+    return CygnusState::Failed;
+  };
+  lock(&session.status).state
+}
+
+/// Copies the 4 bytes of the address assigned by the server to `out`,
+/// all zero before the first challenge. Does nothing if an argument is
+/// `NULL`.
+///
+/// # Safety
+///
+/// `session` must be `NULL` or a live session and `out` `NULL` or point to
+/// 4 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn cygnus_session_client_ip(
+  session: *const CygnusSession,
+  out: *mut u8,
+) {
+  let Some(session) = session.as_ref().filter(|_| !out.is_null()) else {
+    set_last_error("NULL argument");
+    return;
+  };
+  let ip = lock(&session.status).client_ip.octets();
+  std::ptr::copy_nonoverlapping(ip.as_ptr(), out, 4);
+}
+
+/// The error that ended the last session attempt, or `NULL`. To be freed
+/// with `cygnus_string_free`.
+///
+/// # Safety
+///
+/// `session` must be `NULL` or a live session.
+#[no_mangle]
+pub unsafe extern "C" fn cygnus_session_error(
+  session: *const CygnusSession,
+) -> *mut c_char {
+  let Some(session) = session.as_ref() else {
+    set_last_error("NULL argument");
+    return std::ptr::null_mut();
+  };
+  match &lock(&session.status).error {
+    Some(error) => into_c_string(error),
+    None => std::ptr::null_mut(),
+  }
+}
+
+/// Logs out and stops the session thread, waiting for it to finish.
+/// Returns `CYGNUS_STATUS_AUTH` when the server did not confirm the logout,
+/// and `CYGNUS_STATUS_OK` if the session was not online. Called from the
+/// callback, it does nothing and returns `CYGNUS_STATUS_INVALID_ARGUMENT`.
+///
+/// # Safety
+///
+/// `session` must be `NULL` or a live session.
+#[no_mangle]
+pub unsafe extern "C" fn cygnus_session_logout(
+  session: *mut CygnusSession,
+) -> CygnusStatus {
+  let Some(session) = session.as_ref() else {
+    set_last_error("NULL argument");
+    return CygnusStatus::InvalidArgument;
+  };
+  if session.in_callback() {
+    set_last_error("cannot log out from the session callback");
+    return CygnusStatus::InvalidArgument;
+  }
+  session.control.send(Command::Stop);
+  let thread = lock(&session.thread).take();
+  if let Some(thread) = thread {
+    let _ = thread.join();
+  }
+  match &lock(&session.status).logout_error {
+    Some(error) => {
+      set_last_error(error);
+      CygnusStatus::Auth
+    }
+    None => CygnusStatus::Ok,
+  }
+}
+
+/// Frees a session, logging out first if it is still running. Called from
+/// the callback, it does nothing.
+///
+/// # Safety
+///
+/// `session` must be `NULL` or a session that is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn cygnus_session_free(session: *mut CygnusSession) {
+  let Some(live) = session.as_ref() else {
+    return;
+  };
+  if live.in_callback() {
+    set_last_error("cannot free the session from its callback");
+    return;
+  }
+  cygnus_session_logout(session);
+  drop(Box::from_raw(session));
+}
+
+/// The user of a session, handed to [`DrClient::run`] as is.
+struct SessionUser(User);
+
+impl CredentialProvider for SessionUser {
+  fn load(&self) -> UserResult<User> {
+    Ok(self.0.clone())
+  }
+
+  fn describe(&self) -> String {
+    "the C interface".to_string()
+  }
+}
+
+struct Worker {
+  client: DrClient,
+  user: SessionUser,
+  status: Arc<Mutex<SessionStatus>>,
+  callback: CygnusCallback,
+  userdata: Userdata,
+}
+
+impl Worker {
+  fn run(self) {
+    let Err(error) = self.client.run(&self.user, |event| match event {
+      Event::Challenged { client_ip } => {
+        self.set_state(CygnusState::Challenged, Some(*client_ip), None)
+      }
+      Event::Online { client_ip } => {
+        self.set_state(CygnusState::Online, Some(*client_ip), None)
+      }
+      Event::Offline {
+        error: AuthError::StopRequested,
+      } => {}
+      Event::Offline { error } => {
+        self.set_state(CygnusState::Offline, None, Some(error))
+      }
+      _ => {}
+    });
+    match error {
+      AuthError::StopRequested => {}
+      AuthError::AppMaxTriesExceeded => {
+        return self.set_state(CygnusState::Failed, None, None);
+      }
+      // the user always loads, so anything else failed the logout of a stop
+      error => lock(&self.status).logout_error = Some(error.to_string()),
+    }
+    self.set_state(CygnusState::LoggedOut, None, None);
+  }
+
+  fn set_state(
+    &self,
+    state: CygnusState,
+    client_ip: Option<Ipv4Addr>,
+    error: Option<&AuthError>,
+  ) {
+    {
+      let mut status = lock(&self.status);
+      if let Some(client_ip) = client_ip {
+        status.client_ip = client_ip;
+      }
+      if let Some(error) = error {
+        status.error = Some(error.to_string());
+      }
+      if status.state == state {
+        return;
+      }
+      status.state = state;
+    }
+    if let Some(callback) = self.callback {
+      callback(state, self.userdata.0);
+    }
+  }
+}
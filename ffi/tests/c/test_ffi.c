@@ -0,0 +1,105 @@
+/*
+ * Exercises the C interface: test_ffi <server host:port> <user file path>
+ */
+#include <stdatomic.h>
+#include <stdio.h>
+#include <string.h>
+#include <time.h>
+
+#include "cygnus.h"
+
+#define CHECK(cond)                                                          \
+  do {                                                                       \
+    if (!(cond)) {                                                           \
+      const char *error = cygnus_last_error();                               \
+      fprintf(stderr, "%s:%d: check failed: %s (%s)\n", __FILE__, __LINE__,  \
+              #cond, error ? error : "no error");                            \
+      return 1;                                                              \
+    }                                                                        \
+  } while (0)
+
+static atomic_uint seen_states;
+static CygnusSession *session;
+static atomic_int logout_in_callback = -1;
+
+static void on_state(CygnusState state, void *userdata) {
+  atomic_fetch_or(&seen_states, 1u << state);
+  atomic_fetch_add((atomic_uint *)userdata, 1);
+  /* Set by the time it is logged out, and refused instead of waiting for
+   * this thread */
+  if (state == CYGNUS_STATE_LOGGED_OUT) {
+    atomic_store(&logout_in_callback, cygnus_session_logout(session));
+    cygnus_session_free(session);
+  }
+}
+
+static void sleep_ms(long ms) {
+  struct timespec ts = {ms / 1000, (ms % 1000) * 1000000};
+  nanosleep(&ts, NULL);
+}
+
+int main(int argc, char **argv) {
+  CHECK(argc == 3);
+  const char *server = argv[1];
+  const char *path = argv[2];
+
+  /* Invalid input is reported, not fatal */
+  CHECK(cygnus_user_new("user", "password", "00:11:22") == NULL);
+  CHECK(cygnus_last_error() != NULL);
+  CHECK(cygnus_user_new(NULL, "password", "00:11:22:33:44:55") == NULL);
+
+  /* Create, write and read back a user file */
+  CygnusUser *user = cygnus_user_new("user", "password", "00:11:22:33:44:55");
+  CHECK(user != NULL);
+  CHECK(cygnus_user_write(user, path, NULL, false) == CYGNUS_STATUS_OK);
+  CHECK(cygnus_user_write(user, path, NULL, false) == CYGNUS_STATUS_USER);
+  CHECK(cygnus_user_write(user, path, NULL, true) == CYGNUS_STATUS_OK);
+  cygnus_user_free(user);
+
+  user = cygnus_user_read(path, NULL);
+  CHECK(user != NULL);
+  char *username = cygnus_user_username(user);
+  CHECK(strcmp(username, "user") == 0);
+  cygnus_string_free(username);
+  uint8_t mac[6];
+  const uint8_t expected_mac[6] = {0x00, 0x11, 0x22, 0x33, 0x44, 0x55};
+  CHECK(cygnus_user_mac(user, mac) == CYGNUS_STATUS_OK);
+  CHECK(memcmp(mac, expected_mac, 6) == 0);
+
+  /* Go online against the server, then log out */
+  atomic_uint callbacks = 0;
+  CygnusOptions options = cygnus_options_default();
+  options.server = server;
+  options.timeout_ms = 2000;
+  options.max_retries = 0;
+  session = cygnus_session_start(user, &options, on_state, &callbacks);
+  CHECK(session != NULL);
+  cygnus_user_free(user);
+
+  for (int i = 0; i < 500; i++) {
+    if (cygnus_session_state(session) == CYGNUS_STATE_ONLINE) {
+      break;
+    }
+    sleep_ms(10);
+  }
+  CHECK(cygnus_session_state(session) == CYGNUS_STATE_ONLINE);
+  uint8_t ip[4];
+  const uint8_t expected_ip[4] = {10, 0, 0, 7};
+  cygnus_session_client_ip(session, ip);
+  CHECK(memcmp(ip, expected_ip, 4) == 0);
+  CHECK(cygnus_session_error(session) == NULL);
+
+  CHECK(cygnus_session_logout(session) == CYGNUS_STATUS_OK);
+  CHECK(cygnus_session_state(session) == CYGNUS_STATE_LOGGED_OUT);
+  CHECK(atomic_load(&logout_in_callback) == CYGNUS_STATUS_INVALID_ARGUMENT);
+  cygnus_session_free(session);
+
+  unsigned seen = atomic_load(&seen_states);
+  CHECK(seen & (1u << CYGNUS_STATE_CHALLENGED));
+  CHECK(seen & (1u << CYGNUS_STATE_ONLINE));
+  CHECK(seen & (1u << CYGNUS_STATE_LOGGED_OUT));
+  CHECK(atomic_load(&callbacks) >= 3);
+
+  printf("C interface ok\n");
+  return 0;
+}
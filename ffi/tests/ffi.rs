@@ -0,0 +1,71 @@
+//! Builds `tests/c/test_ffi.c` against the shared library and runs it
+//! against a mock server.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+#[allow(dead_code)]
+#[path = "../../src/testing/fixtures.rs"]
+mod fixtures;
+
+use fixtures::{temp_path, MockServer};
+
+#[test]
+fn test_header_up_to_date() {
+  let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  let generated =
+    std::fs::read_to_string(PathBuf::from(env!("OUT_DIR")).join("cygnus.h"))
+      .unwrap();
+  let checked_in =
+    std::fs::read_to_string(manifest.join("include/cygnus.h")).unwrap();
+  assert!(
+    generated == checked_in,
+    "include/cygnus.h is out of date, copy it from {}",
+    env!("OUT_DIR")
+  );
+}
+
+#[test]
+fn test_c_program() {
+  let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  // the library is built next to this test, in target/<profile>/deps, and
+  // only copied to target/<profile> by `cargo build`
+  let lib_dir = std::env::current_exe()
+    .unwrap()
+    .parent()
+    .unwrap()
+    .to_path_buf();
+  let work = temp_path("ffi");
+  let _ = std::fs::remove_dir_all(&work);
+  std::fs::create_dir_all(&work).unwrap();
+  let exe = work.join("test_ffi");
+
+  let status = Command::new(std::env::var("CC").unwrap_or("cc".to_string()))
+    .arg(manifest.join("tests/c/test_ffi.c"))
+    .arg("-I")
+    .arg(manifest.join("include"))
+    .arg("-L")
+    .arg(&lib_dir)
+    .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+    .arg("-lcygnus")
+    .arg("-o")
+    .arg(&exe)
+    .status()
+    .unwrap();
+  assert!(status.success(), "failed to compile the C test");
+
+  let server = MockServer::accepting();
+  // cargo also puts target/<profile> on the library path, which would win
+  // over the rpath with a stale copy
+  let output = Command::new(&exe)
+    .env_remove("LD_LIBRARY_PATH")
+    .arg(server.addr())
+    .arg(work.join("user.usr"))
+    .output()
+    .unwrap();
+  print!("{}", String::from_utf8_lossy(&output.stdout));
+  eprint!("{}", String::from_utf8_lossy(&output.stderr));
+  assert!(output.status.success());
+
+  std::fs::remove_dir_all(&work).unwrap();
+}
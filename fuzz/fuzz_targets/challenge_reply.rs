@@ -0,0 +1,11 @@
+#![no_main]
+
+use cygnus::auth::reply::ChallengeReply;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+  if let Some(reply) = ChallengeReply::parse(data) {
+    assert!(data.len() >= 24);
+    assert_eq!(reply.salt, data[4..8]);
+  }
+});
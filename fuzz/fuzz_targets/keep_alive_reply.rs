@@ -0,0 +1,15 @@
+#![no_main]
+
+use cygnus::auth::reply::KeepAliveReply;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+  if let Some(reply) = KeepAliveReply::parse(data) {
+    assert_eq!(reply.version().is_some(), data.len() >= 30);
+    assert_eq!(reply.tail().is_some(), data.len() >= 20);
+  }
+  let mut request = [0; 40];
+  request[..data.len().min(40)].copy_from_slice(&data[..data.len().min(40)]);
+  let _ = KeepAliveReply::answers_38(data);
+  let _ = KeepAliveReply::answers_40(data, &request);
+});
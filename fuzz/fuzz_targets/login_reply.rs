@@ -0,0 +1,11 @@
+#![no_main]
+
+use cygnus::auth::reply::LoginReply;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+  if let Some(LoginReply::Success { tail, account }) = LoginReply::parse(data) {
+    assert_eq!(tail, data[23..39]);
+    let _ = account.map(|info| info.to_string());
+  }
+});
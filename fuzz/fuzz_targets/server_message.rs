@@ -0,0 +1,10 @@
+#![no_main]
+
+use cygnus::auth::message::ServerMessage;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+  if let ServerMessage::Reply(reply) = ServerMessage::parse(data) {
+    assert_eq!(reply, data);
+  }
+});
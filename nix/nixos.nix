@@ -42,11 +42,12 @@ let
             # ExecStart = "${cygnus-rs}/bin/cygnus auth -f ${cfg.userFile}";
             Restart = "on-failure";
             RestartSec = 5;
+            LoadCredential = "cygnus.usr:${cfg.userFile}";
           };
 
           script = ''
             if [[ -r ${cfg.userFile} ]]; then
-              ${cygnus-rs}/bin/cygnus auth -f ${cfg.userFile}
+              ${cygnus-rs}/bin/cygnus auth --credentials systemd:cygnus.usr
             fi
           '';
         };
@@ -0,0 +1,270 @@
+//! Python bindings, built into the `cygnus` module with maturin.
+
+use std::time::Duration;
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use cygnus::auth::{self, DrClient, Session};
+use cygnus::user::{self, cipher::UserCipher, recipient, User};
+
+create_exception!(cygnus, CygnusError, PyException, "Base of all errors.");
+create_exception!(
+  cygnus,
+  UserError,
+  CygnusError,
+  "Invalid user data or a user file that cannot be read."
+);
+create_exception!(
+  cygnus,
+  AuthError,
+  CygnusError,
+  "Talking to the authentication server failed."
+);
+
+fn user_error(e: user::UserError) -> PyErr {
+  UserError::new_err(e.to_string())
+}
+
+fn auth_error(e: auth::AuthError) -> PyErr {
+  AuthError::new_err(e.to_string())
+}
+
+/// The credentials of a campus network account.
+#[pyclass(name = "User", module = "cygnus", frozen, eq)]
+#[derive(Clone, PartialEq)]
+struct PyUser(User);
+
+#[pymethods]
+impl PyUser {
+  /// `mac` is written as `xx:xx:xx:xx:xx:xx`.
+  #[new]
+  fn new(username: String, password: &str, mac: &str) -> PyResult<Self> {
+    let mac = User::transform_mac(mac).map_err(user_error)?;
+    User::new(username, password.into(), mac)
+      .map(Self)
+      .map_err(user_error)
+  }
+
+  #[getter]
+  fn username(&self) -> &str {
+    &self.0.username
+  }
+
+  #[getter]
+  fn mac(&self) -> String {
+    User::format_mac(&self.0.mac)
+  }
+
+  fn __repr__(&self) -> String {
+    format!("User(username={:?}, mac={:?})", self.0.username, self.mac())
+  }
+}
+
+/// Reads and writes the contents of user files.
+#[pyclass(name = "UserCipher", module = "cygnus", frozen)]
+struct PyUserCipher;
+
+#[pymethods]
+impl PyUserCipher {
+  /// Encrypts a user, to age or SSH public keys if `recipients` are given.
+  #[staticmethod]
+  #[pyo3(signature = (user, recipients = None))]
+  fn encrypt<'py>(
+    py: Python<'py>,
+    user: &PyUser,
+    recipients: Option<Vec<String>>,
+  ) -> PyResult<Bound<'py, PyBytes>> {
+    let buffer =
+      user::encrypt_user(user.0.clone(), &recipients.unwrap_or_default())
+        .map_err(user_error)?;
+    Ok(PyBytes::new(py, &buffer))
+  }
+
+  /// Decrypts a user, with the identity file at `identity` for data
+  /// encrypted to recipients.
+  #[staticmethod]
+  #[pyo3(signature = (data, identity = None))]
+  fn decrypt(data: &[u8], identity: Option<&str>) -> PyResult<PyUser> {
+    let identities = match identity {
+      Some(identity) => {
+        recipient::load_identities(identity).map_err(user_error)?
+      }
+      None => Vec::new(),
+    };
+    UserCipher::decrypt_with_identities(data, &identities)
+      .map(PyUser)
+      .map_err(user_error)
+  }
+}
+
+/// A session with the authentication server.
+///
+/// Call `keep_alive_once()` about every 20 seconds after `login()` to stay
+/// online. Used as a context manager, the session logs out on exit.
+#[pyclass(name = "Session", module = "cygnus")]
+struct PySession {
+  session: Session,
+  server: String,
+  state: &'static str,
+}
+
+impl PySession {
+  fn require(&self, state: &str, action: &str) -> PyResult<()> {
+    match self.state == state {
+      true => Ok(()),
+      false => Err(AuthError::new_err(format!(
+        "cannot {} a session that is {}",
+        action, self.state
+      ))),
+    }
+  }
+
+  /// Runs a step without holding the GIL, going offline if it fails.
+  fn step<T: Send>(
+    &mut self,
+    py: Python<'_>,
+    f: impl FnOnce(&mut Session) -> auth::AuthResult<T> + Send,
+  ) -> PyResult<T> {
+    let session = &mut self.session;
+    py.allow_threads(|| f(session)).map_err(|e| {
+      self.state = "offline";
+      auth_error(e)
+    })
+  }
+}
+
+#[pymethods]
+impl PySession {
+  /// `server` is `host:port` and defaults to the JLU server, `timeout` is in
+  /// seconds.
+  #[new]
+  #[pyo3(signature = (user, server = None, timeout = 5.0))]
+  fn new(
+    user: &PyUser,
+    server: Option<String>,
+    timeout: f64,
+  ) -> PyResult<Self> {
+    let server = server.unwrap_or(DrClient::DEFAULT_SERVER.to_string());
+    let timeout = Duration::try_from_secs_f64(timeout)
+      .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+    let session = DrClient::builder()
+      .server(server.as_str())
+      .timeout(timeout)
+      .build()
+      .connect(user.0.clone())
+      .map_err(auth_error)?;
+    Ok(Self {
+      session,
+      server,
+      state: "connected",
+    })
+  }
+
+  /// Challenges the server and logs in, returning the assigned address.
+  fn login(&mut self, py: Python<'_>) -> PyResult<String> {
+    self.require("connected", "log in")?;
+    let client_ip = self.step(py, |session| {
+      let client_ip = session.challenge()?;
+      session.login()?;
+      Ok(client_ip)
+    })?;
+    self.state = "online";
+    Ok(client_ip.to_string())
+  }
+
+  /// Runs one keep alive round.
+  fn keep_alive_once(&mut self, py: Python<'_>) -> PyResult<()> {
+    self.require("online", "keep alive")?;
+    self.step(py, Session::keep_alive)
+  }
+
+  fn logout(&mut self, py: Python<'_>) -> PyResult<()> {
+    self.require("online", "log out")?;
+    self.step(py, Session::logout)?;
+    self.state = "logged_out";
+    Ok(())
+  }
+
+  /// One of `connected`, `online`, `offline` (a step failed) and
+  /// `logged_out`.
+  #[getter]
+  fn state(&self) -> &'static str {
+    self.state
+  }
+
+  #[getter]
+  fn online(&self) -> bool {
+    self.state == "online"
+  }
+
+  /// The address assigned by the server, `None` before login.
+  #[getter]
+  fn client_ip(&self) -> Option<String> {
+    let client_ip = self.session.client_ip();
+    (!client_ip.is_unspecified()).then(|| client_ip.to_string())
+  }
+
+  /// Usage reported by the server at login, a dict with `used_minutes`,
+  /// `used_traffic_kib` and `balance` (in yuan), `None` before login.
+  #[getter]
+  fn account<'py>(
+    &self,
+    py: Python<'py>,
+  ) -> PyResult<Option<Bound<'py, PyDict>>> {
+    let Some(info) = self.session.account() else {
+      return Ok(None);
+    };
+    let dict = PyDict::new(py);
+    dict.set_item("used_minutes", info.used_minutes)?;
+    dict.set_item("used_traffic_kib", info.used_traffic_kib)?;
+    dict.set_item("balance", info.balance())?;
+    Ok(Some(dict))
+  }
+
+  /// Number of accepted keep alive rounds.
+  #[getter]
+  fn rounds(&self) -> u64 {
+    self.session.rounds()
+  }
+
+  #[getter]
+  fn server(&self) -> &str {
+    &self.server
+  }
+
+  fn __enter__(slf: Py<Self>) -> Py<Self> {
+    slf
+  }
+
+  #[pyo3(signature = (*_args))]
+  fn __exit__(
+    &mut self,
+    py: Python<'_>,
+    _args: &Bound<'_, pyo3::types::PyTuple>,
+  ) -> PyResult<bool> {
+    if self.online() {
+      self.logout(py)?;
+    }
+    Ok(false)
+  }
+
+  fn __repr__(&self) -> String {
+    format!("Session(server={:?}, state={:?})", self.server, self.state)
+  }
+}
+
+#[pymodule]
+#[pyo3(name = "cygnus")]
+pub fn cygnus_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+  m.add_class::<PyUser>()?;
+  m.add_class::<PyUserCipher>()?;
+  m.add_class::<PySession>()?;
+  m.add("CygnusError", m.py().get_type::<CygnusError>())?;
+  m.add("UserError", m.py().get_type::<UserError>())?;
+  m.add("AuthError", m.py().get_type::<AuthError>())?;
+  m.add("DEFAULT_SERVER", DrClient::DEFAULT_SERVER)?;
+  Ok(())
+}
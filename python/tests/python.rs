@@ -0,0 +1,33 @@
+//! Runs `test_cygnus.py` in an embedded interpreter.
+
+use pyo3::ffi::c_str;
+use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyModule};
+
+#[test]
+fn test_python() {
+  Python::with_gil(|py| -> PyResult<()> {
+    let module = pyo3::wrap_pymodule!(cygnus_python::cygnus_module)(py);
+    py.import("sys")?
+      .getattr("modules")?
+      .set_item("cygnus", module)?;
+
+    let tests = PyModule::from_code(
+      py,
+      c_str!(include_str!("test_cygnus.py")),
+      c_str!("test_cygnus.py"),
+      c_str!("test_cygnus"),
+    )?;
+    let unittest = py.import("unittest")?;
+    let suite = unittest
+      .getattr("defaultTestLoader")?
+      .call_method1("loadTestsFromModule", (tests,))?;
+    let result = unittest
+      .getattr("TextTestRunner")?
+      .call((), Some(&[("verbosity", 2)].into_py_dict(py)?))?
+      .call_method1("run", (suite,))?;
+    assert!(result.call_method0("wasSuccessful")?.extract::<bool>()?);
+    Ok(())
+  })
+  .unwrap();
+}
@@ -0,0 +1,126 @@
+"""Tests of the bindings against a local mock server.
+
+Run with `cargo test -p cygnus-python`, or `python -m unittest` after
+`maturin develop`.
+"""
+
+import socket
+import threading
+import unittest
+
+import cygnus
+
+MAC = "00:11:22:33:44:55"
+
+
+class MockServer:
+    """Answers challenge, login, keep alive and logout packets."""
+
+    def __init__(self, accept=True):
+        self.accept = accept
+        self.packets = []
+        self.socket = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+        self.socket.bind(("127.0.0.1", 0))
+        self.address = "%s:%d" % self.socket.getsockname()
+        self.thread = threading.Thread(target=self.serve, daemon=True)
+        self.thread.start()
+
+    def serve(self):
+        while True:
+            try:
+                data, peer = self.socket.recvfrom(400)
+            except OSError:
+                return
+            self.packets.append(data[0])
+            reply = bytearray(64)
+            if data[0] == 0x01:
+                reply[0] = 0x02
+                reply[4:8] = b"salt"
+                reply[20:24] = bytes([10, 0, 0, 7])
+            elif data[0] == 0x03:
+                reply[0] = 0x04 if self.accept else 0x05
+                reply[5:9] = (90).to_bytes(4, "little")
+                reply[13:17] = (1234).to_bytes(4, "little")
+            elif data[0] == 0x06:
+                reply[0] = 0x04
+            elif data[0] == 0xFF:
+                reply[0] = 0x07
+            elif data[0] == 0x07:
+                # echo the counter and move to the next step
+                reply = bytearray(40)
+                reply[0:6] = bytes([0x07, data[1], 0x28, 0x00, 0x0B, data[5] + 1])
+            else:
+                continue
+            self.socket.sendto(bytes(reply), peer)
+
+    def close(self):
+        self.socket.close()
+
+
+class UserTest(unittest.TestCase):
+    def test_user(self):
+        user = cygnus.User("user", "password", MAC)
+        self.assertEqual(user.username, "user")
+        self.assertEqual(user.mac, MAC)
+        self.assertNotIn("password", repr(user))
+
+    def test_invalid_user(self):
+        with self.assertRaises(cygnus.UserError):
+            cygnus.User("user", "password", "00:11")
+        with self.assertRaises(cygnus.CygnusError):
+            cygnus.User("user", "p" * 17, MAC)
+
+    def test_cipher(self):
+        user = cygnus.User("user", "password", MAC)
+        data = cygnus.UserCipher.encrypt(user)
+        self.assertIsInstance(data, bytes)
+        self.assertEqual(cygnus.UserCipher.decrypt(data), user)
+        with self.assertRaises(cygnus.UserError):
+            cygnus.UserCipher.decrypt(data[:40])
+
+
+class SessionTest(unittest.TestCase):
+    def setUp(self):
+        self.user = cygnus.User("user", "password", MAC)
+
+    def test_session(self):
+        server = MockServer()
+        self.addCleanup(server.close)
+        session = cygnus.Session(self.user, server=server.address, timeout=2)
+        self.assertEqual(session.state, "connected")
+        self.assertIsNone(session.client_ip)
+        self.assertIsNone(session.account)
+
+        self.assertEqual(session.login(), "10.0.0.7")
+        self.assertTrue(session.online)
+        self.assertEqual(session.client_ip, "10.0.0.7")
+        self.assertEqual(session.account["used_minutes"], 90)
+        self.assertAlmostEqual(session.account["balance"], 12.34)
+        session.keep_alive_once()
+        session.keep_alive_once()
+        self.assertEqual(session.rounds, 2)
+
+        session.logout()
+        self.assertEqual(session.state, "logged_out")
+        self.assertEqual(server.packets[-1], 0x06)
+        with self.assertRaises(cygnus.AuthError):
+            session.keep_alive_once()
+
+    def test_rejected(self):
+        server = MockServer(accept=False)
+        self.addCleanup(server.close)
+        session = cygnus.Session(self.user, server=server.address, timeout=2)
+        with self.assertRaisesRegex(cygnus.AuthError, "password"):
+            session.login()
+        self.assertEqual(session.state, "offline")
+
+    def test_context_manager(self):
+        server = MockServer()
+        self.addCleanup(server.close)
+        with cygnus.Session(self.user, server=server.address) as session:
+            session.login()
+        self.assertEqual(session.state, "logged_out")
+
+
+if __name__ == "__main__":
+    unittest.main()
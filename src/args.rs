@@ -1,10 +1,15 @@
 pub use clap::{Parser, Subcommand};
 
 use crate::auth::args::AuthArgs;
+use crate::doctor::args::DoctorArgs;
+use crate::logging::args::LogArgs;
 use crate::user::args::UserArgs;
 
 #[derive(Parser)]
 pub struct Args {
+  #[command(flatten)]
+  pub log: LogArgs,
+
   #[command(subcommand)]
   pub command: ArgsCommand,
 }
@@ -12,8 +17,11 @@ pub struct Args {
 #[derive(Subcommand)]
 pub enum ArgsCommand {
   /// Authenticate a user
-  Auth(AuthArgs),
+  Auth(Box<AuthArgs>),
 
   /// Operate on user authentication files
   User(UserArgs),
+
+  /// Check the setup and the route to the server
+  Doctor(DoctorArgs),
 }
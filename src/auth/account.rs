@@ -0,0 +1,124 @@
+//! Usage of the account, as reported in the login reply.
+
+use std::fmt;
+
+use serde::Serialize;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct AccountInfo {
+  /// Time online in the current billing period, in minutes.
+  pub used_minutes: u32,
+  /// Traffic in the current billing period, in KiB.
+  pub used_traffic_kib: u32,
+  /// Remaining balance, in cents (0.01 yuan).
+  pub balance_cents: u32,
+}
+
+impl AccountInfo {
+  pub fn used_traffic_mib(&self) -> f64 {
+    f64::from(self.used_traffic_kib) / 1024.0
+  }
+
+  pub fn balance(&self) -> f64 {
+    f64::from(self.balance_cents) / 100.0
+  }
+}
+
+impl fmt::Display for AccountInfo {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "used {} minutes and {:.2} MiB, balance {:.2} yuan",
+      self.used_minutes,
+      self.used_traffic_mib(),
+      self.balance()
+    )
+  }
+}
+
+/// Limits below which the account usage is worth a warning.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AccountAlerts {
+  /// Warn when the balance drops below this many yuan.
+  pub min_balance: Option<f64>,
+  /// Traffic included in the plan, in MiB.
+  pub traffic_quota: Option<f64>,
+  /// Warn when less than this many MiB of the quota are left.
+  pub min_traffic: Option<f64>,
+}
+
+impl AccountAlerts {
+  /// Describes every limit `info` is below.
+  pub fn check(&self, info: &AccountInfo) -> Vec<String> {
+    let mut alerts = Vec::new();
+    if let Some(min) = self.min_balance.filter(|&min| info.balance() < min) {
+      alerts.push(format!(
+        "balance {:.2} yuan is below {:.2} yuan",
+        info.balance(),
+        min
+      ));
+    }
+    if let (Some(quota), Some(min)) = (self.traffic_quota, self.min_traffic) {
+      let left = quota - info.used_traffic_mib();
+      if left < min {
+        alerts.push(format!(
+          "{:.2} MiB of traffic left, below {:.2} MiB",
+          left.max(0.0),
+          min
+        ));
+      }
+    }
+    alerts
+  }
+
+  /// Logs a warning for every limit `info` is below.
+  pub fn warn(&self, info: &AccountInfo) {
+    for alert in self.check(info) {
+      warn!(
+        balance = info.balance(),
+        used_traffic_mib = info.used_traffic_mib(),
+        "Account {}",
+        alert
+      );
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_account_info() {
+    let info = AccountInfo {
+      used_minutes: 1500,
+      used_traffic_kib: 300 * 1024,
+      balance_cents: 850,
+    };
+    assert_eq!(
+      info.to_string(),
+      "used 1500 minutes and 300.00 MiB, balance 8.50 yuan"
+    );
+
+    let alerts = AccountAlerts {
+      min_balance: Some(10.0),
+      traffic_quota: Some(1024.0),
+      min_traffic: Some(512.0),
+    };
+    assert_eq!(
+      alerts.check(&info),
+      vec!["balance 8.50 yuan is below 10.00 yuan"]
+    );
+    let info = AccountInfo {
+      used_traffic_kib: 900 * 1024,
+      balance_cents: 2000,
+      ..info
+    };
+    assert_eq!(
+      alerts.check(&info),
+      vec!["124.00 MiB of traffic left, below 512.00 MiB"]
+    );
+    assert!(AccountAlerts::default().check(&info).is_empty());
+  }
+}
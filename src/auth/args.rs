@@ -1,15 +1,64 @@
 use clap::Parser;
-use clap::ValueEnum;
-use tracing::Level;
+
+use super::budget::{parse_duration, BudgetPeriod};
+use super::client::DrClient;
+use super::schedule::Window;
+
 #[derive(Parser)]
 pub struct AuthArgs {
-  /// Specify the user authentication file (generated by `user` subcommand)
+  /// Specify the user authentication file (generated by `user` subcommand);
+  /// repeat to fail over to the next account when one is refused
   #[arg(short, long)]
-  pub file: String,
+  pub file: Vec<String>,
+
+  /// Use a named user from the user store (defaults to the only entry of
+  /// the store when no other source is given); separate several with commas
+  /// or repeat to fail over to the next account when one is refused
+  #[arg(short, long, value_delimiter = ',', conflicts_with = "file")]
+  pub user: Vec<String>,
+
+  /// Credential source: `file:<path>`, `store:[name]`, `systemd:[name]`,
+  /// `env:[prefix]`, `stdin:` or `cmd:<command>`; repeat to fail over to the
+  /// next account when one is refused
+  #[arg(long, conflicts_with_all = ["file", "user"])]
+  pub credentials: Vec<String>,
+
+  /// Wait this long before trying an account the server refused again, when
+  /// there are several, such as `10m`
+  #[arg(long, default_value = "10m", value_parser = parse_duration)]
+  pub account_cooldown: std::time::Duration,
 
-  /// Log level
-  #[clap(short, long, default_value = "info")]
-  pub log_level: LogLevel,
+  /// Address of the authentication server, as `host:port`; separate
+  /// several with commas or repeat to fail over when one stops answering
+  #[arg(
+    short,
+    long,
+    value_delimiter = ',',
+    default_value = DrClient::DEFAULT_SERVER
+  )]
+  pub server: Vec<String>,
+
+  /// Watch this network interface: pause keep alive while its link is
+  /// down and log in again as soon as it is back or its address changes
+  #[cfg(all(target_os = "linux", feature = "netlink"))]
+  #[arg(short = 'I', long)]
+  pub interface: Option<String>,
+
+  /// Serve the HTTP management API, with the bearer token taken from
+  /// `--http-token-file` or `CYGNUS_HTTP_TOKEN`
+  #[cfg(feature = "http")]
+  #[arg(long)]
+  pub http: bool,
+
+  /// Address of the HTTP management API
+  #[cfg(feature = "http")]
+  #[arg(long, default_value = crate::http::DEFAULT_LISTEN, requires = "http")]
+  pub http_listen: String,
+
+  /// File holding the bearer token of the HTTP management API
+  #[cfg(feature = "http")]
+  #[arg(long, requires = "http")]
+  pub http_token_file: Option<String>,
 
   /// Timeout for udp connection, in seconds
   #[clap(short, long, default_value = "5")]
@@ -22,25 +71,163 @@ pub struct AuthArgs {
   /// Retry delay for authentication, in milliseconds
   #[clap(short, long, default_value = "500")]
   pub delay: u64,
+
+  /// Only be online inside this window, such as `mon-fri 07:00-23:30` or
+  /// `sat,sun 22:00-02:00`; repeat for several windows
+  #[arg(long = "window", value_name = "WINDOW")]
+  pub windows: Vec<Window>,
+
+  /// Log out once this much time was spent online, such as `4h` or `1h30m`
+  #[arg(long, value_parser = parse_duration)]
+  pub budget: Option<std::time::Duration>,
+
+  /// Period of the online time budget: `daily` or `monthly`
+  #[arg(long, default_value = "daily", requires = "budget")]
+  pub budget_period: BudgetPeriod,
+
+  /// File keeping the online time used so far
+  #[arg(long, requires = "budget")]
+  pub budget_file: Option<String>,
+
+  /// Warn when the account balance drops below this many yuan
+  #[arg(long, value_name = "YUAN")]
+  pub warn_balance: Option<f64>,
+
+  /// Traffic included in the plan of the account, in MiB
+  #[arg(long, value_name = "MIB")]
+  pub traffic_quota: Option<f64>,
+
+  /// Warn when less than this many MiB of the traffic quota are left
+  #[arg(long, value_name = "MIB", requires = "traffic_quota")]
+  pub warn_traffic: Option<f64>,
+
+  /// Run this shell command after each login, with the event described in
+  /// `CYGNUS_*` variables
+  #[arg(long, value_name = "COMMAND")]
+  pub on_login: Option<String>,
+
+  /// Run this shell command after logging out by the schedule, the budget
+  /// or a command
+  #[arg(long, value_name = "COMMAND")]
+  pub on_logout: Option<String>,
+
+  /// Run this shell command when an online session fails
+  #[arg(long, value_name = "COMMAND")]
+  pub on_keepalive_fail: Option<String>,
+
+  /// Run this shell command when any session fails
+  #[arg(long, value_name = "COMMAND")]
+  pub on_error: Option<String>,
+
+  /// Kill hook commands still running after this long, such as `30s`
+  #[arg(long, default_value = "30s", value_parser = parse_duration)]
+  pub hook_timeout: std::time::Duration,
+
+  /// Build every packet of a session and print them as annotated hex dumps
+  /// instead of connecting
+  #[arg(long)]
+  pub dry_run: bool,
+
+  /// Salt of the challenge reply to use in the dry run, as 4 hex bytes
+  #[arg(long, value_name = "HEX", value_parser = parse_salt, requires = "dry_run")]
+  pub salt: Option<[u8; 4]>,
+
+  /// Client IP of the challenge reply to use in the dry run
+  #[arg(long, requires = "dry_run")]
+  pub client_ip: Option<std::net::Ipv4Addr>,
+
+  /// Login success reply to use in the dry run, in hex
+  #[arg(long, value_name = "HEX", value_parser = parse_hex, requires = "dry_run")]
+  pub login_reply: Option<HexBytes>,
+
+  /// Keep alive reply to the 38 byte packet to use in the dry run, in hex
+  #[arg(long, value_name = "HEX", value_parser = parse_hex, requires = "dry_run")]
+  pub keep_alive_reply: Option<HexBytes>,
+
+  /// Seed of the random bytes in the dry run
+  #[arg(long, default_value = "0", requires = "dry_run")]
+  pub seed: u64,
+
+  /// Identity (age identity file or SSH private key) for user files
+  /// encrypted to recipients
+  #[arg(short, long)]
+  pub identity: Option<String>,
+
+  /// Refuse user files readable by group or others or owned by another user
+  #[clap(long)]
+  pub strict_perms: bool,
+
+  /// Lock the memory holding passwords and login packets to keep them out of swap
+  #[clap(long)]
+  pub lock_memory: bool,
 }
 
-#[derive(Debug, ValueEnum, Clone)]
-pub enum LogLevel {
-  Trace,
-  Debug,
-  Info,
-  Warn,
-  Error,
+/// Bytes given in hex, a single value rather than a list of bytes to clap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexBytes(pub Vec<u8>);
+
+/// Parses hex bytes, allowing spaces and colons between them.
+fn parse_hex(s: &str) -> Result<HexBytes, String> {
+  let digits = s
+    .chars()
+    .filter(|c| !c.is_whitespace() && *c != ':')
+    .collect::<Vec<_>>();
+  if digits.len() % 2 != 0 {
+    return Err("odd number of hex digits".to_string());
+  }
+  digits
+    .chunks(2)
+    .map(|pair| {
+      let pair = pair.iter().collect::<String>();
+      u8::from_str_radix(&pair, 16)
+        .map_err(|_| format!("invalid hex: {}", pair))
+    })
+    .collect::<Result<_, _>>()
+    .map(HexBytes)
 }
 
-impl From<LogLevel> for Level {
-  fn from(level: LogLevel) -> Self {
-    match level {
-      LogLevel::Trace => Level::TRACE,
-      LogLevel::Debug => Level::DEBUG,
-      LogLevel::Info => Level::INFO,
-      LogLevel::Warn => Level::WARN,
-      LogLevel::Error => Level::ERROR,
+fn parse_salt(s: &str) -> Result<[u8; 4], String> {
+  let HexBytes(bytes) = parse_hex(s)?;
+  bytes
+    .try_into()
+    .map_err(|b: Vec<u8>| format!("the salt has 4 bytes, not {}", b.len()))
+}
+
+impl AuthArgs {
+  /// The token of the HTTP management API.
+  #[cfg(feature = "http")]
+  pub fn http_token(&self) -> std::io::Result<String> {
+    match &self.http_token_file {
+      Some(path) => Ok(std::fs::read_to_string(path)?.trim().to_string()),
+      None => std::env::var("CYGNUS_HTTP_TOKEN").map_err(|_| {
+        std::io::Error::new(
+          std::io::ErrorKind::NotFound,
+          "no token for the HTTP API, use --http-token-file or \
+           CYGNUS_HTTP_TOKEN",
+        )
+      }),
+    }
+  }
+
+  /// The credential URIs selected by `--credentials`, `--file` or `--user`,
+  /// in order of preference.
+  pub fn credentials_uris(&self) -> Vec<String> {
+    if !self.credentials.is_empty() {
+      self.credentials.clone()
+    } else if !self.file.is_empty() {
+      self
+        .file
+        .iter()
+        .map(|file| format!("file:{}", file))
+        .collect()
+    } else if !self.user.is_empty() {
+      self
+        .user
+        .iter()
+        .map(|user| format!("store:{}", user))
+        .collect()
+    } else {
+      vec!["store:".to_string()]
     }
   }
 }
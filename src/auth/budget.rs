@@ -0,0 +1,310 @@
+//! A daily or monthly limit on the time spent online, kept in a small JSON
+//! file so restarts do not reset it.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::user::perms::write_private_atomic;
+
+use super::error::AuthResult;
+use super::schedule::LocalTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BudgetPeriod {
+  /// Resets at local midnight.
+  #[default]
+  Daily,
+  /// Resets on the first day of each month.
+  Monthly,
+}
+
+impl BudgetPeriod {
+  /// Identifies the period `now` is in, `YYYY-MM-DD` or `YYYY-MM`.
+  fn key(&self, now: &LocalTime) -> String {
+    match self {
+      Self::Daily => {
+        format!("{:04}-{:02}-{:02}", now.year, now.month, now.day)
+      }
+      Self::Monthly => format!("{:04}-{:02}", now.year, now.month),
+    }
+  }
+
+  pub fn until_reset(&self, now: &LocalTime) -> Duration {
+    match self {
+      Self::Daily => now.until_midnight(),
+      Self::Monthly => now.until_next_month(),
+    }
+  }
+}
+
+impl FromStr for BudgetPeriod {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "daily" => Ok(Self::Daily),
+      "monthly" => Ok(Self::Monthly),
+      _ => Err(format!("invalid budget period `{}`", s)),
+    }
+  }
+}
+
+impl fmt::Display for BudgetPeriod {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Daily => f.write_str("daily"),
+      Self::Monthly => f.write_str("monthly"),
+    }
+  }
+}
+
+/// Parses a duration such as `4h`, `90m` or `1h30m`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+  let invalid = || format!("invalid duration `{}`, expected e.g. `1h30m`", s);
+  let mut secs = 0u64;
+  let mut digits = String::new();
+  for c in s.chars() {
+    if c.is_ascii_digit() {
+      digits.push(c);
+      continue;
+    }
+    let unit = match c {
+      'd' => 86400,
+      'h' => 3600,
+      'm' => 60,
+      's' => 1,
+      _ => return Err(invalid()),
+    };
+    let value = digits.parse::<u64>().map_err(|_| invalid())?;
+    secs = value
+      .checked_mul(unit)
+      .and_then(|value| secs.checked_add(value))
+      .ok_or_else(invalid)?;
+    digits.clear();
+  }
+  if !digits.is_empty() || secs == 0 {
+    return Err(invalid());
+  }
+  Ok(Duration::from_secs(secs))
+}
+
+/// How much time may be spent online per period, and where the usage is
+/// stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Budget {
+  limit: Duration,
+  period: BudgetPeriod,
+  path: PathBuf,
+}
+
+impl Budget {
+  /// A budget stored at [`default_path`](Self::default_path).
+  pub fn new(limit: Duration, period: BudgetPeriod) -> Self {
+    Self {
+      limit,
+      period,
+      path: Self::default_path(),
+    }
+  }
+
+  /// Stores the usage at `path` instead.
+  pub fn path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+    self.path = path.into();
+    self
+  }
+
+  pub fn limit(&self) -> Duration {
+    self.limit
+  }
+
+  pub fn period(&self) -> BudgetPeriod {
+    self.period
+  }
+
+  /// `$STATE_DIRECTORY/budget.json` under systemd, `/var/lib/cygnus` for
+  /// root and `$XDG_STATE_HOME/cygnus` otherwise.
+  pub fn default_path() -> PathBuf {
+    let dir = std::env::var_os("STATE_DIRECTORY")
+      .filter(|dir| !dir.is_empty())
+      .map(PathBuf::from)
+      .or_else(|| {
+        crate::user::store::is_root().then(|| PathBuf::from("/var/lib/cygnus"))
+      })
+      .or_else(|| {
+        std::env::var_os("XDG_STATE_HOME")
+          .filter(|dir| !dir.is_empty())
+          .map(|dir| PathBuf::from(dir).join("cygnus"))
+      })
+      .or_else(|| {
+        std::env::var_os("HOME")
+          .map(|home| PathBuf::from(home).join(".local/state/cygnus"))
+      })
+      .unwrap_or_else(|| PathBuf::from("."));
+    dir.join("budget.json")
+  }
+
+  /// Reads the usage recorded so far. A missing or unreadable file starts
+  /// from zero.
+  pub fn load(&self) -> BudgetUsage {
+    let state = match std::fs::read(&self.path) {
+      Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+        warn!(
+          "Ignoring invalid budget file {}: {}",
+          self.path.display(),
+          e
+        );
+        BudgetState::default()
+      }),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => BudgetState::default(),
+      Err(e) => {
+        warn!("Failed to read budget file {}: {}", self.path.display(), e);
+        BudgetState::default()
+      }
+    };
+    BudgetUsage {
+      budget: self.clone(),
+      state,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct BudgetState {
+  period: String,
+  used_ms: u64,
+}
+
+/// The time spent online in the current period of a [`Budget`].
+#[derive(Debug)]
+pub struct BudgetUsage {
+  budget: Budget,
+  state: BudgetState,
+}
+
+impl BudgetUsage {
+  /// Starts over when `now` is in a new period.
+  fn roll(&mut self, now: &LocalTime) {
+    let key = self.budget.period.key(now);
+    if self.state.period != key {
+      if !self.state.period.is_empty() {
+        info!(period = key, "Online time budget reset");
+      }
+      self.state = BudgetState {
+        period: key,
+        used_ms: 0,
+      };
+    }
+  }
+
+  pub fn used(&mut self, now: &LocalTime) -> Duration {
+    self.roll(now);
+    Duration::from_millis(self.state.used_ms)
+  }
+
+  pub fn remaining(&mut self, now: &LocalTime) -> Duration {
+    self.budget.limit.saturating_sub(self.used(now))
+  }
+
+  pub fn until_reset(&self, now: &LocalTime) -> Duration {
+    self.budget.period.until_reset(now)
+  }
+
+  /// Adds `elapsed` to the current period and saves the usage.
+  pub fn record(
+    &mut self,
+    elapsed: Duration,
+    now: &LocalTime,
+  ) -> AuthResult<()> {
+    self.roll(now);
+    self.state.used_ms += elapsed.as_millis() as u64;
+    self.save()
+  }
+
+  fn save(&self) -> AuthResult<()> {
+    let path = &self.budget.path;
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+      std::fs::create_dir_all(dir)?;
+    }
+    let data = serde_json::to_vec(&self.state).map_err(io::Error::from)?;
+    write_private_atomic(&path.to_string_lossy(), &data)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::temp_path;
+
+  fn at(month: u32, day: u32, hour: u32) -> LocalTime {
+    LocalTime {
+      year: 2024,
+      month,
+      day,
+      weekday: 0,
+      minute: hour * 60,
+      second: 0,
+      unix: None,
+    }
+  }
+
+  #[test]
+  fn test_parse_duration() {
+    assert_eq!(parse_duration("4h"), Ok(Duration::from_secs(4 * 3600)));
+    assert_eq!(parse_duration("90m"), Ok(Duration::from_secs(90 * 60)));
+    assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+    assert_eq!(parse_duration("1d12h"), Ok(Duration::from_secs(36 * 3600)));
+    for invalid in ["", "10", "0h", "h", "1x", "1.5h", "99999999999999999d"] {
+      assert!(parse_duration(invalid).is_err(), "{}", invalid);
+    }
+  }
+
+  #[test]
+  fn test_budget_usage() {
+    let dir = temp_path("budget");
+    let path = dir.join("state").join("budget.json");
+    let budget =
+      Budget::new(Duration::from_secs(3600), BudgetPeriod::Daily).path(&path);
+
+    let mut usage = budget.load();
+    assert_eq!(usage.remaining(&at(9, 2, 8)), Duration::from_secs(3600));
+    usage
+      .record(Duration::from_secs(3000), &at(9, 2, 9))
+      .unwrap();
+
+    // persisted across restarts
+    let mut usage = budget.load();
+    assert_eq!(usage.remaining(&at(9, 2, 10)), Duration::from_secs(600));
+    usage
+      .record(Duration::from_secs(900), &at(9, 2, 11))
+      .unwrap();
+    assert_eq!(usage.remaining(&at(9, 2, 11)), Duration::ZERO);
+    assert_eq!(
+      usage.until_reset(&at(9, 2, 11)),
+      Duration::from_secs(13 * 3600)
+    );
+
+    // a new day starts over
+    assert_eq!(usage.remaining(&at(9, 3, 0)), Duration::from_secs(3600));
+
+    // the monthly period keeps counting across days
+    let monthly =
+      Budget::new(Duration::from_secs(3600), BudgetPeriod::Monthly).path(&path);
+    let mut usage = monthly.load();
+    usage.record(Duration::from_secs(60), &at(9, 3, 0)).unwrap();
+    assert_eq!(usage.used(&at(9, 30, 0)), Duration::from_secs(60));
+    assert_eq!(usage.used(&at(10, 1, 0)), Duration::ZERO);
+
+    // garbage is ignored
+    std::fs::write(&path, b"not json").unwrap();
+    assert_eq!(monthly.load().used(&at(9, 3, 0)), Duration::ZERO);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,964 @@
+use std::cell::Cell;
+use std::convert::Infallible;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use tracing::{error, info, warn};
+
+use crate::user::{provider::CredentialProvider, User};
+
+use super::{
+  account::AccountAlerts,
+  budget::Budget,
+  context::DrContext,
+  control::{Command, Controller, State},
+  env::{DrEnv, SystemEnv},
+  error::{AuthError, AuthResult},
+  failover::Accounts,
+  hook::{Hook, HookContext, Hooks},
+  policy::Policy,
+  schedule::{LocalTime, Schedule},
+  session::{Event, Session},
+};
+
+#[cfg(all(target_os = "linux", feature = "netlink"))]
+use super::link::LinkWatcher;
+
+/// How often a failed session is started again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+  /// Retries after the first session, `None` retries forever.
+  pub max_retries: Option<u64>,
+  /// Pause before each retry.
+  pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      max_retries: None,
+      delay: Duration::from_millis(500),
+    }
+  }
+}
+
+/// A configured client for the Dr.COM server.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use cygnus::auth::DrClient;
+/// use cygnus::user::User;
+///
+/// let user = User::new(
+///   "user".into(),
+///   "password".into(),
+///   [0x00, 0x11, 0x22, 0x33, 0x44, 0x55],
+/// )?;
+/// let client = DrClient::builder().timeout(Duration::from_secs(3)).build();
+/// let mut session = client.connect(user)?;
+/// let client_ip = session.challenge()?;
+/// session.login()?;
+/// println!("Online as {}", client_ip);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct DrClient {
+  servers: Vec<String>,
+  timeout: Duration,
+  challenge_tries: u8,
+  keep_alive_interval: Duration,
+  retry: RetryPolicy,
+  schedule: Schedule,
+  budget: Option<Budget>,
+  alerts: AccountAlerts,
+  control: Option<Controller>,
+  hooks: Hooks,
+  account_cooldown: Duration,
+  #[cfg(all(target_os = "linux", feature = "netlink"))]
+  interface: Option<String>,
+}
+
+impl DrClient {
+  /// The authentication server of JLU.
+  pub const DEFAULT_SERVER: &'static str = "10.100.61.3:61440";
+
+  /// Longest sleep while idle, so clock changes and suspends are noticed.
+  const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+  /// Longest wait for link changes before looking for a command.
+  #[cfg(all(target_os = "linux", feature = "netlink"))]
+  const CONTROL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+  pub fn builder() -> DrClientBuilder {
+    DrClientBuilder::default()
+  }
+
+  /// The first server, used by [`connect`](Self::connect).
+  pub fn server(&self) -> &str {
+    &self.servers[0]
+  }
+
+  /// The servers [`run`](Self::run) rotates through.
+  pub fn servers(&self) -> &[String] {
+    &self.servers
+  }
+
+  pub fn timeout(&self) -> Duration {
+    self.timeout
+  }
+
+  /// Time between keep alive rounds.
+  pub fn keep_alive_interval(&self) -> Duration {
+    self.keep_alive_interval
+  }
+
+  pub fn retry(&self) -> RetryPolicy {
+    self.retry
+  }
+
+  /// Opens a session for `user` with the first server. No packet is sent
+  /// yet.
+  pub fn connect(&self, user: User) -> AuthResult<Session> {
+    self.connect_with_env(user, SystemEnv)
+  }
+
+  pub fn connect_with_env<E: DrEnv>(
+    &self,
+    user: User,
+    env: E,
+  ) -> AuthResult<Session<E>> {
+    self.connect_to(user, self.server(), env)
+  }
+
+  /// Resolves `server`, which may be a hostname, and opens a session with
+  /// its first IPv4 address.
+  fn connect_to<E: DrEnv>(
+    &self,
+    user: User,
+    server: &str,
+    env: E,
+  ) -> AuthResult<Session<E>> {
+    let addr = resolve(server)?;
+    let ctx = DrContext::try_new_with_env(user, addr, self.timeout, env)?;
+    Ok(Session::new(
+      ctx,
+      self.challenge_tries,
+      self.keep_alive_interval,
+    ))
+  }
+
+  /// Keeps a user online, starting a new session with fresh credentials from
+  /// `provider` whenever one fails. Outside the [`Schedule`] or once the
+  /// [`Budget`] is used up, it logs out and idles until it may log in again.
+  /// Only returns when the credentials cannot be loaded, the retries of the
+  /// [`RetryPolicy`] are used up, or a [`Command::Stop`] ends it with
+  /// [`AuthError::StopRequested`], or the error of its logout.
+  pub fn run(
+    &self,
+    provider: &dyn CredentialProvider,
+    on_event: impl FnMut(&Event),
+  ) -> AuthResult<Infallible> {
+    self.run_accounts(&[provider], on_event)
+  }
+
+  /// Like [`run`](Self::run), with several accounts in order of preference.
+  /// When the server refuses an account, by its credentials or its state
+  /// such as being in use or out of balance, the next one is used and the
+  /// refused one cools down for the [account
+  /// cooldown](DrClientBuilder::account_cooldown). Each session starts with
+  /// the first account not cooling down, waiting when all of them are.
+  /// Accounts whose credentials cannot be loaded are skipped the same way,
+  /// it only returns the load error once none of them can be loaded.
+  #[tracing::instrument(skip_all, name = "auth")]
+  pub fn run_accounts(
+    &self,
+    providers: &[&dyn CredentialProvider],
+    mut on_event: impl FnMut(&Event),
+  ) -> AuthResult<Infallible> {
+    if providers.is_empty() {
+      return Err(AuthError::NoAccounts);
+    }
+    #[cfg(all(target_os = "linux", feature = "netlink"))]
+    let mut watcher = match &self.interface {
+      Some(interface) => Some(LinkWatcher::new(interface)?),
+      None => None,
+    };
+    let mut policy = Policy::new(&self.schedule, self.budget.as_ref());
+    let mut emit = |event: &Event| {
+      if let Some(control) = &self.control {
+        control.observe(event);
+      }
+      on_event(event);
+    };
+
+    let mut retry_times = self.retry.max_retries;
+    let mut server = 0;
+    let mut attempt = 0;
+    let mut accounts = Accounts::new(providers.len(), self.account_cooldown);
+    let mut account = None;
+    let mut unloadable = vec![false; providers.len()];
+    loop {
+      self.idle(&mut policy, &mut emit);
+      if let Some(control) = &self.control {
+        control.wait_login();
+        if control.stopped() {
+          return Err(AuthError::StopRequested);
+        }
+        control.set_state(State::Connecting);
+      }
+
+      #[cfg(all(target_os = "linux", feature = "netlink"))]
+      if let Some(watcher) = watcher.as_mut().filter(|w| !w.is_up()) {
+        warn!(interface = watcher.interface(), "Waiting for link up");
+        match watcher.wait_up(|| self.check_control()) {
+          // the top of the loop acts on the command
+          Err(
+            AuthError::LogoutRequested
+            | AuthError::ReconnectRequested
+            | AuthError::StopRequested,
+          ) => continue,
+          result => result?,
+        }
+      }
+
+      let index = self.select_account(&accounts)?;
+      if account != Some(index) {
+        account = Some(index);
+        let source = providers[index].describe();
+        if providers.len() > 1 {
+          info!(
+            account = index + 1,
+            "Using account {} from {}",
+            index + 1,
+            source
+          );
+        }
+        emit(&Event::UsingAccount { index, source });
+      }
+      let user = match self.load(providers[index]) {
+        Ok(user) => user,
+        Err(error) => {
+          unloadable[index] = true;
+          if unloadable.iter().all(|u| *u) {
+            return Err(error);
+          }
+          accounts.reject(index, Instant::now());
+          warn!(
+            account = index + 1,
+            "Failed to load account: {}, trying it again in {} seconds",
+            error,
+            self.account_cooldown.as_secs()
+          );
+          continue;
+        }
+      };
+      unloadable[index] = false;
+      attempt += 1;
+      let mut hook_context = HookContext {
+        username: user.username.clone(),
+        server: self.servers[server].clone(),
+        attempt,
+        ..HookContext::default()
+      };
+      let mut online = false;
+      // a failure to resolve or connect is the server's, like a silent one
+      let mut unreachable = false;
+      let error = match self.start(user, &self.servers[server]) {
+        Ok(mut session) => {
+          if let Some(control) = &self.control {
+            control.set_server(session.server());
+          }
+          info!("Starting authentication process");
+          // only time after a successful login counts against the budget
+          let online_since = Cell::new(None);
+          let on_session_event = |event: &Event| {
+            match event {
+              Event::Challenged { client_ip } => {
+                hook_context.client_ip = Some(*client_ip)
+              }
+              Event::Online { .. } => {
+                online = true;
+                online_since.set(Some(Instant::now()));
+                self.hooks.fire(Hook::Login, &hook_context);
+              }
+              Event::Account { info } => self.alerts.warn(info),
+              _ => {}
+            }
+            emit(event);
+          };
+          let Err(error) =
+            session.run_with(on_session_event, |env, interval| {
+              if let Some(since) = online_since.take() {
+                policy.online(since);
+              }
+              let interval = match policy.online_left(&LocalTime::now()) {
+                Some(left) => interval.min(left),
+                None => interval,
+              };
+              let paused = self.pause(
+                env,
+                interval,
+                #[cfg(all(target_os = "linux", feature = "netlink"))]
+                watcher.as_mut(),
+              );
+              let now = LocalTime::now();
+              policy.record(&now, true);
+              paused?;
+              policy.check(&now)
+            });
+          if let Some(since) = online_since.take() {
+            policy.online(since);
+          }
+          policy.record(&LocalTime::now(), false);
+
+          if let AuthError::OutsideSchedule
+          | AuthError::BudgetExhausted
+          | AuthError::LogoutRequested
+          | AuthError::StopRequested = error
+          {
+            info!(state = "offline", "{}, logging out", error);
+            let logout = session.logout();
+            if let Err(e) = &logout {
+              warn!("Failed to log out: {}", e);
+            }
+            self
+              .hooks
+              .fire(Hook::Logout, &hook_context.with_error(&error));
+            let stop = matches!(error, AuthError::StopRequested);
+            emit(&Event::Offline { error });
+            if stop {
+              return Err(logout.err().unwrap_or(AuthError::StopRequested));
+            }
+            attempt = 0;
+            continue;
+          }
+          error
+        }
+        Err(error) => {
+          unreachable = true;
+          error
+        }
+      };
+
+      if online {
+        attempt = 0;
+      }
+      if let AuthError::NetworkChanged | AuthError::ReconnectRequested = error {
+        info!(state = "offline", "{}", error);
+        emit(&Event::Offline { error });
+        continue;
+      }
+      error!(state = "offline", "Authentication failed: {}", error);
+      let hook_context = hook_context.with_error(&error);
+      if online {
+        self.hooks.fire(Hook::KeepAliveFail, &hook_context);
+      }
+      self.hooks.fire(Hook::Error, &hook_context);
+      let unreachable =
+        unreachable || matches!(error, AuthError::ChallengeMaxTriesExceeded);
+      if error.is_account_error() && providers.len() > 1 {
+        accounts.reject(index, Instant::now());
+        warn!(
+          account = index + 1,
+          "Account refused, trying it again in {} seconds",
+          self.account_cooldown.as_secs()
+        );
+      }
+      emit(&Event::Offline { error });
+      if unreachable && self.servers.len() > 1 {
+        server = (server + 1) % self.servers.len();
+        warn!(
+          server = self.servers[server],
+          "Switching to the next server"
+        );
+      }
+
+      if let Some(retry) = retry_times {
+        if retry == 0 {
+          error!("App max tries exceeded");
+          return Err(AuthError::AppMaxTriesExceeded);
+        }
+        retry_times = Some(retry - 1);
+      }
+      info!("Retrying in {} milliseconds", self.retry.delay.as_millis());
+      emit(&Event::Retrying {
+        delay: self.retry.delay,
+      });
+      self.sleep(self.retry.delay);
+    }
+  }
+
+  /// Sleeps while the policy keeps the client offline, until stopped.
+  fn idle(&self, policy: &mut Policy, on_event: &mut impl FnMut(&Event)) {
+    let mut idling = false;
+    while let Some((reason, duration)) = policy.blocked(&LocalTime::now()) {
+      if self.stopped() {
+        return;
+      }
+      if !idling {
+        info!(
+          state = "idle",
+          "{}, idle for {} seconds",
+          reason,
+          duration.as_secs()
+        );
+        on_event(&Event::Idle { duration });
+        idling = true;
+      }
+      self.sleep(duration.min(Self::IDLE_CHECK_INTERVAL));
+    }
+  }
+
+  /// The account to log in with, waiting while all of them cool down.
+  fn select_account(&self, accounts: &Accounts) -> AuthResult<usize> {
+    loop {
+      match accounts.select(Instant::now()) {
+        Ok(index) => return Ok(index),
+        Err(_) if self.stopped() => return Err(AuthError::StopRequested),
+        Err(wait) => {
+          warn!(
+            "All accounts were refused, waiting {} seconds",
+            wait.as_secs()
+          );
+          self.sleep(wait);
+        }
+      }
+    }
+  }
+
+  fn stopped(&self) -> bool {
+    self.control.as_ref().is_some_and(Controller::stopped)
+  }
+
+  /// Sleeps outside of a session, waking up early for a command.
+  fn sleep(&self, duration: Duration) {
+    match &self.control {
+      Some(control) => {
+        control.wait(duration);
+      }
+      None => std::thread::sleep(duration),
+    }
+  }
+
+  /// Waits between keep alive rounds. A link change or a command ends the
+  /// session with an error.
+  fn pause(
+    &self,
+    env: &SystemEnv,
+    interval: Duration,
+    #[cfg(all(target_os = "linux", feature = "netlink"))] mut watcher: Option<
+      &mut LinkWatcher,
+    >,
+  ) -> AuthResult<()> {
+    let Some(control) = &self.control else {
+      #[cfg(all(target_os = "linux", feature = "netlink"))]
+      if let Some(watcher) = watcher {
+        return watcher.pause(interval, || Ok(()));
+      }
+      env.sleep(interval);
+      return Ok(());
+    };
+
+    let deadline = Instant::now() + interval;
+    loop {
+      let left = deadline.saturating_duration_since(Instant::now());
+      if left.is_zero() {
+        return Ok(());
+      }
+      #[cfg(all(target_os = "linux", feature = "netlink"))]
+      let command = match watcher.as_deref_mut() {
+        Some(watcher) => {
+          watcher.pause(left.min(Self::CONTROL_CHECK_INTERVAL), || {
+            self.check_control()
+          })?;
+          control.wait(Duration::ZERO)
+        }
+        None => control.wait(left),
+      };
+      #[cfg(not(all(target_os = "linux", feature = "netlink")))]
+      let command = control.wait(left);
+      command_result(command)?;
+    }
+  }
+
+  /// Fails with the request of a pending command, for waits that end early.
+  #[cfg(all(target_os = "linux", feature = "netlink"))]
+  fn check_control(&self) -> AuthResult<()> {
+    match &self.control {
+      Some(control) => command_result(control.wait(Duration::ZERO)),
+      None => Ok(()),
+    }
+  }
+
+  #[tracing::instrument(skip_all, name = "context")]
+  fn load(&self, provider: &dyn CredentialProvider) -> AuthResult<User> {
+    info!("Reading user data from {}", provider.describe());
+    let user = provider.load()?;
+    info!(user = %user.username_hash(), "Loaded credentials");
+    Ok(user)
+  }
+
+  #[tracing::instrument(skip_all, name = "context", fields(server = server))]
+  fn start(&self, user: User, server: &str) -> AuthResult<Session> {
+    let session = self.connect_to(user, server, SystemEnv)?;
+    info!(addr = %session.server(), "Using server {}", server);
+    Ok(session)
+  }
+}
+
+/// The error a command ends a session with, if it does.
+fn command_result(command: Option<Command>) -> AuthResult<()> {
+  match command {
+    Some(Command::Logout) => Err(AuthError::LogoutRequested),
+    Some(Command::Reconnect) => Err(AuthError::ReconnectRequested),
+    Some(Command::Stop) => Err(AuthError::StopRequested),
+    Some(Command::Login) | None => Ok(()),
+  }
+}
+
+/// Resolves `server` (`host:port`) to its first IPv4 address, again on every
+/// call so DNS changes are picked up by the next session.
+pub(crate) fn resolve(server: &str) -> AuthResult<SocketAddr> {
+  let addrs = match server.to_socket_addrs() {
+    Ok(addrs) => addrs,
+    Err(e) => {
+      warn!(server, "Failed to resolve server: {}", e);
+      return Err(AuthError::UnresolvedServer(server.to_string()));
+    }
+  };
+  addrs
+    .into_iter()
+    .find(SocketAddr::is_ipv4)
+    .ok_or_else(|| AuthError::UnresolvedServer(server.to_string()))
+}
+
+impl Default for DrClient {
+  fn default() -> Self {
+    Self {
+      servers: vec![Self::DEFAULT_SERVER.to_string()],
+      timeout: Duration::from_secs(5),
+      challenge_tries: 5,
+      keep_alive_interval: Duration::from_secs(20),
+      retry: RetryPolicy::default(),
+      schedule: Schedule::default(),
+      budget: None,
+      alerts: AccountAlerts::default(),
+      control: None,
+      hooks: Hooks::default(),
+      account_cooldown: Duration::from_secs(600),
+      #[cfg(all(target_os = "linux", feature = "netlink"))]
+      interface: None,
+    }
+  }
+}
+
+/// Builds a [`DrClient`], starting from the defaults of the JLU campus.
+#[derive(Debug, Clone, Default)]
+pub struct DrClientBuilder {
+  client: DrClient,
+}
+
+impl DrClientBuilder {
+  /// The server address, as `host:port` with a hostname or an IP.
+  pub fn server<S: Into<String>>(mut self, server: S) -> Self {
+    self.client.servers = vec![server.into()];
+    self
+  }
+
+  /// Several servers, tried in turn when one stops answering challenges.
+  /// An empty list keeps the current servers.
+  pub fn servers<I, S>(mut self, servers: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    let servers = servers.into_iter().map(Into::into).collect::<Vec<_>>();
+    if !servers.is_empty() {
+      self.client.servers = servers;
+    }
+    self
+  }
+
+  /// Read and write timeout of the UDP socket.
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.client.timeout = timeout;
+    self
+  }
+
+  /// Number of challenge packets sent before giving up.
+  pub fn challenge_tries(mut self, tries: u8) -> Self {
+    self.client.challenge_tries = tries;
+    self
+  }
+
+  /// Pause between keep alive rounds in [`Session::run`].
+  pub fn keep_alive_interval(mut self, interval: Duration) -> Self {
+    self.client.keep_alive_interval = interval;
+    self
+  }
+
+  pub fn retry(mut self, retry: RetryPolicy) -> Self {
+    self.client.retry = retry;
+    self
+  }
+
+  /// Only be online inside these windows. The default empty schedule is
+  /// always open.
+  pub fn schedule(mut self, schedule: Schedule) -> Self {
+    self.client.schedule = schedule;
+    self
+  }
+
+  /// Log out once this much time was spent online in the current period.
+  pub fn budget(mut self, budget: Budget) -> Self {
+    self.client.budget = Some(budget);
+    self
+  }
+
+  /// Warn when the account usage reported at login crosses these limits.
+  pub fn alerts(mut self, alerts: AccountAlerts) -> Self {
+    self.client.alerts = alerts;
+    self
+  }
+
+  /// Run these commands when the session logs in, logs out or fails.
+  pub fn hooks(mut self, hooks: Hooks) -> Self {
+    self.client.hooks = hooks;
+    self
+  }
+
+  /// How long [`run_accounts`](DrClient::run_accounts) waits before trying an
+  /// account the server refused again.
+  pub fn account_cooldown(mut self, cooldown: Duration) -> Self {
+    self.client.account_cooldown = cooldown;
+    self
+  }
+
+  /// Report the state to `control` and follow its commands.
+  pub fn control(mut self, control: Controller) -> Self {
+    self.client.control = Some(control);
+    self
+  }
+
+  /// Watch this interface in [`DrClient::run`]: keep alive pauses while its
+  /// link is down, and a link or address change logs in again immediately.
+  #[cfg(all(target_os = "linux", feature = "netlink"))]
+  pub fn watch_interface<S: Into<String>>(mut self, interface: S) -> Self {
+    self.client.interface = Some(interface.into());
+    self
+  }
+
+  pub fn build(self) -> DrClient {
+    self.client
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::UdpSocket;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::Arc;
+
+  use super::*;
+  use crate::testing::{reply, temp_path, FixedUser, MockServer};
+
+  #[test]
+  fn test_resolve() {
+    assert_eq!(
+      resolve("localhost:61440").unwrap(),
+      "127.0.0.1:61440".parse().unwrap()
+    );
+    assert!(matches!(
+      resolve("[::1]:61440"),
+      Err(AuthError::UnresolvedServer(_))
+    ));
+  }
+
+  #[test]
+  fn test_server_failover() {
+    // nothing listens on a port just released
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let dead = silent.local_addr().unwrap().to_string();
+    drop(silent);
+
+    let server = MockServer::rejecting();
+    // connecting to the broadcast address is refused without SO_BROADCAST
+    let broadcast = "255.255.255.255:61440";
+    let client = DrClient::builder()
+      .servers([dead.as_str(), "invalid host", broadcast, server.addr()])
+      .timeout(Duration::from_millis(50))
+      .challenge_tries(1)
+      .retry(RetryPolicy {
+        max_retries: Some(3),
+        delay: Duration::ZERO,
+      })
+      .build();
+    assert_eq!(client.server(), dead);
+
+    let mut events = Vec::new();
+    let Err(error) = client.run(&FixedUser("user"), |event| {
+      events.push(format!("{:?}", event));
+    });
+    assert!(matches!(error, AuthError::AppMaxTriesExceeded));
+    let offline = events
+      .iter()
+      .filter(|e| e.starts_with("Offline"))
+      .collect::<Vec<_>>();
+    assert!(offline[0].contains("ChallengeMaxTriesExceeded"));
+    assert!(offline[1].contains("UnresolvedServer"));
+    assert!(offline[2].starts_with("Offline { error: Io("));
+    assert!(offline[3].contains("InvalidUsernameOrPassword"));
+  }
+
+  #[test]
+  fn test_controlled_run() {
+    let logouts = Arc::new(AtomicUsize::new(0));
+    let counter = logouts.clone();
+    let server = MockServer::new(move |request| {
+      if request[0] == 0x06 {
+        counter.fetch_add(1, Ordering::SeqCst);
+      }
+      reply(request, true)
+    });
+    let control = Controller::new();
+    let client = DrClient::builder()
+      .server(server.addr())
+      .timeout(Duration::from_secs(2))
+      .keep_alive_interval(Duration::from_secs(60))
+      .control(control.clone())
+      .build();
+    let handle =
+      std::thread::spawn(move || client.run(&FixedUser("user"), |_| {}));
+
+    let wait_for = |state: State| {
+      for _ in 0..500 {
+        if control.status().state == state {
+          return;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+      }
+      panic!("never reached {:?}", state);
+    };
+    wait_for(State::Online);
+    assert_eq!(control.status().server, server.addr().parse().ok());
+    control.send(Command::Logout);
+    wait_for(State::LoggedOut);
+    assert_eq!(logouts.load(Ordering::SeqCst), 1);
+    assert_eq!(control.status().client_ip, None);
+
+    control.send(Command::Login);
+    wait_for(State::Online);
+    control.send(Command::Reconnect);
+    for _ in 0..500 {
+      let error = control.status().last_error;
+      if error.as_deref() == Some("Reconnect requested") {
+        break;
+      }
+      std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(
+      control.status().last_error.as_deref(),
+      Some("Reconnect requested")
+    );
+    wait_for(State::Online);
+    assert_eq!(logouts.load(Ordering::SeqCst), 1);
+
+    control.send(Command::Stop);
+    let Err(error) = handle.join().unwrap();
+    assert!(matches!(error, AuthError::StopRequested));
+    assert_eq!(logouts.load(Ordering::SeqCst), 2);
+  }
+
+  #[test]
+  fn test_failed_login_is_not_charged() {
+    let path = temp_path("charged.json");
+    let _ = std::fs::remove_file(&path);
+    // the login times out, so the session spends a while before failing
+    let server = MockServer::new(|request| match request[0] {
+      0x03 => None,
+      _ => reply(request, true),
+    });
+    let budget =
+      Budget::new(Duration::from_secs(3600), crate::auth::BudgetPeriod::Daily)
+        .path(&path);
+    let client = DrClient::builder()
+      .server(server.addr())
+      .timeout(Duration::from_millis(200))
+      .retry(RetryPolicy {
+        max_retries: Some(0),
+        delay: Duration::ZERO,
+      })
+      .budget(budget.clone())
+      .build();
+    assert!(client.run(&FixedUser("user"), |_| {}).is_err());
+    assert_eq!(budget.load().used(&LocalTime::now()), Duration::ZERO);
+    let _ = std::fs::remove_file(&path);
+  }
+
+  #[test]
+  fn test_account_failover() {
+    let server = MockServer::new(|request| match request {
+      [0x03, ..] if request[20..24] == *b"busy" => {
+        let mut in_use = vec![0; 64];
+        in_use[..5].copy_from_slice(&[0x05, 0, 0, 0, 0x01]);
+        Some(in_use)
+      }
+      _ => reply(request, true),
+    });
+    let control = Controller::new();
+    let client = DrClient::builder()
+      .server(server.addr())
+      .timeout(Duration::from_secs(2))
+      .keep_alive_interval(Duration::from_secs(60))
+      .retry(RetryPolicy {
+        max_retries: None,
+        delay: Duration::ZERO,
+      })
+      .control(control.clone())
+      .build();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+      client.run_accounts(&[&FixedUser("busy"), &FixedUser("free")], |event| {
+        let _ = sender.send(format!("{:?}", event));
+      })
+    });
+
+    let mut events = Vec::new();
+    while !events.iter().any(|e: &String| e.starts_with("Online")) {
+      events.push(receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+    }
+    let events = events
+      .iter()
+      .filter(|e| !e.starts_with("Challenged") && !e.starts_with("Account"))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      events[0],
+      "UsingAccount { index: 0, source: \"fixed busy\" }"
+    );
+    assert_eq!(events[1], "Offline { error: AccountInUse }");
+    assert!(events[2].starts_with("Retrying"));
+    assert_eq!(
+      events[3],
+      "UsingAccount { index: 1, source: \"fixed free\" }"
+    );
+    assert!(events[4].starts_with("Online"));
+    assert_eq!(control.status().account.as_deref(), Some("fixed free"));
+  }
+
+  #[test]
+  fn test_account_load_failover() {
+    // longer than the 36 bytes a username may have, so it fails to load
+    let invalid = FixedUser("0123456789012345678901234567890123456789");
+    let client = DrClient::builder().server("127.0.0.1:9").build();
+    assert!(matches!(
+      client.run_accounts(&[], |_| {}),
+      Err(AuthError::NoAccounts)
+    ));
+    assert!(matches!(
+      client.run_accounts(&[&invalid, &invalid], |_| {}),
+      Err(AuthError::User(_))
+    ));
+
+    let server = MockServer::accepting();
+    let client = DrClient::builder()
+      .server(server.addr())
+      .timeout(Duration::from_secs(2))
+      .keep_alive_interval(Duration::from_secs(60))
+      .build();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+      client.run_accounts(&[&invalid, &FixedUser("free")], |event| {
+        let _ = sender.send(format!("{:?}", event));
+      })
+    });
+    let mut events = Vec::new();
+    while !events.iter().any(|e: &String| e.starts_with("Online")) {
+      events.push(receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+    }
+    let accounts = events
+      .iter()
+      .filter(|e| e.starts_with("UsingAccount"))
+      .collect::<Vec<_>>();
+    assert_eq!(accounts.len(), 2);
+    assert!(accounts[0].starts_with("UsingAccount { index: 0"));
+    assert_eq!(
+      accounts[1],
+      "UsingAccount { index: 1, source: \"fixed free\" }"
+    );
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_run_hooks() {
+    let path = temp_path("hooks.log");
+    let _ = std::fs::remove_file(&path);
+    let line = |event: &str| {
+      format!(
+        "echo \"{} $CYGNUS_USERNAME $CYGNUS_CLIENT_IP $CYGNUS_ERROR_KIND \
+         $CYGNUS_ATTEMPT\" >> {}",
+        event,
+        path.display()
+      )
+    };
+    let hooks = Hooks {
+      on_login: Some(line("login")),
+      on_logout: Some(line("logout")),
+      on_keepalive_fail: Some(line("keepalive_fail")),
+      on_error: Some(line("error")),
+      ..Hooks::default()
+    };
+    let read_lines = |count: usize| {
+      for _ in 0..500 {
+        let text = std::fs::read_to_string(&path).unwrap_or_default();
+        let mut lines = text.lines().map(String::from).collect::<Vec<_>>();
+        if lines.len() >= count {
+          lines.sort();
+          return lines;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+      }
+      panic!("fewer than {} hooks ran", count);
+    };
+
+    let server = MockServer::rejecting();
+    let client = DrClient::builder()
+      .server(server.addr())
+      .timeout(Duration::from_secs(2))
+      .retry(RetryPolicy {
+        max_retries: Some(1),
+        delay: Duration::ZERO,
+      })
+      .hooks(hooks.clone())
+      .build();
+    assert!(client.run(&FixedUser("user"), |_| {}).is_err());
+    assert_eq!(
+      read_lines(2),
+      [
+        "error user 10.0.0.7 invalid_username_or_password 1",
+        "error user 10.0.0.7 invalid_username_or_password 2",
+      ]
+    );
+    std::fs::remove_file(&path).unwrap();
+
+    let server = MockServer::accepting();
+    let control = Controller::new();
+    let client = DrClient::builder()
+      .server(server.addr())
+      .timeout(Duration::from_secs(2))
+      .keep_alive_interval(Duration::from_secs(60))
+      .control(control.clone())
+      .hooks(hooks)
+      .build();
+    std::thread::spawn(move || client.run(&FixedUser("user"), |_| {}));
+    read_lines(1);
+    control.send(Command::Logout);
+    assert_eq!(
+      read_lines(2),
+      [
+        "login user 10.0.0.7  1",
+        "logout user 10.0.0.7 logout_requested 1"
+      ]
+    );
+    std::fs::remove_file(&path).unwrap();
+  }
+}
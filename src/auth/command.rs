@@ -0,0 +1,116 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::user::{
+  provider::{provider_from_uri, ProviderOptions},
+  secret,
+};
+
+use super::{
+  account::AccountAlerts,
+  args::AuthArgs,
+  budget::Budget,
+  client::{DrClient, RetryPolicy},
+  dry_run::{dry_run, DryRunOptions},
+  error::AuthResult,
+  hook::Hooks,
+  schedule::Schedule,
+};
+
+pub fn auth_command_resolver(args: AuthArgs) -> AuthResult<()> {
+  if args.lock_memory {
+    match secret::lock_memory() {
+      Ok(_) => info!("Locking credentials in memory"),
+      Err(e) => warn!("Failed to lock memory: {}", e),
+    }
+  }
+
+  let options = ProviderOptions {
+    strict_perms: args.strict_perms,
+    identity: args.identity.clone(),
+  };
+  let providers = args
+    .credentials_uris()
+    .iter()
+    .map(|uri| provider_from_uri(uri, &options))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  if args.dry_run {
+    let options = DryRunOptions {
+      salt: args.salt.unwrap_or_default(),
+      client_ip: args.client_ip.unwrap_or(Ipv4Addr::UNSPECIFIED),
+      login_reply: args.login_reply.map(|hex| hex.0),
+      keep_alive_reply: args.keep_alive_reply.map(|hex| hex.0),
+      seed: args.seed,
+    };
+    return dry_run(
+      providers[0].load()?,
+      &options,
+      &mut std::io::stdout().lock(),
+    );
+  }
+
+  #[cfg(feature = "http")]
+  let control = match args.http {
+    true => {
+      let control = super::control::Controller::new();
+      crate::http::serve(
+        &args.http_listen,
+        args.http_token()?,
+        control.clone(),
+      )?;
+      Some(control)
+    }
+    false => None,
+  };
+
+  let builder = DrClient::builder()
+    .servers(args.server)
+    .timeout(Duration::from_secs(args.timeout))
+    .retry(RetryPolicy {
+      max_retries: args.retry,
+      delay: Duration::from_millis(args.delay),
+    })
+    .schedule(Schedule::new(args.windows))
+    .alerts(AccountAlerts {
+      min_balance: args.warn_balance,
+      traffic_quota: args.traffic_quota,
+      min_traffic: args.warn_traffic,
+    })
+    .hooks(Hooks {
+      on_login: args.on_login,
+      on_logout: args.on_logout,
+      on_keepalive_fail: args.on_keepalive_fail,
+      on_error: args.on_error,
+      timeout: args.hook_timeout,
+    })
+    .account_cooldown(args.account_cooldown);
+  let builder = match args.budget {
+    Some(limit) => {
+      let budget = Budget::new(limit, args.budget_period);
+      builder.budget(match args.budget_file {
+        Some(path) => budget.path(path),
+        None => budget,
+      })
+    }
+    None => builder,
+  };
+  #[cfg(all(target_os = "linux", feature = "netlink"))]
+  let builder = match args.interface {
+    Some(interface) => builder.watch_interface(interface),
+    None => builder,
+  };
+  #[cfg(feature = "http")]
+  let builder = match control {
+    Some(control) => builder.control(control),
+    None => builder,
+  };
+  let client = builder.build();
+
+  // The client logs every event itself
+  let providers = providers.iter().map(Box::as_ref).collect::<Vec<_>>();
+  let Err(e) = client.run_accounts(&providers, |_| {});
+  Err(e)
+}
@@ -1,41 +1,104 @@
-use std::net::UdpSocket;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+
+use zeroize::Zeroizing;
 
 use crate::user::User;
 
 use super::{
   data::{self, AliveType, DrContextData},
+  env::{DrEnv, SystemEnv},
   error::AuthResult,
 };
 
-pub struct DrContext {
+/// More than the longest login packet, which has a 16 byte password.
+const LOGIN_BUFFER_LEN: usize = 400;
+
+/// A [`DrPackets`] with the socket its packets are exchanged on.
+pub struct DrContext<E: DrEnv = SystemEnv> {
   pub client: UdpSocket,
+  /// The address `client` is connected to.
+  pub server: SocketAddr,
+  pub packets: DrPackets<E>,
+}
+
+/// Builds the packets of a session from the user, the state the server
+/// handed out and the environment. It has no socket, so packets can be built
+/// without touching the network.
+pub struct DrPackets<E: DrEnv = SystemEnv> {
   pub data: DrContextData,
   pub user: User,
+  pub env: E,
 }
 
 impl DrContext {
-  pub fn try_new(user: User, timeout: u64) -> AuthResult<Self> {
+  pub fn try_new<A: ToSocketAddrs>(
+    user: User,
+    server: A,
+    timeout: Duration,
+  ) -> AuthResult<Self> {
+    Self::try_new_with_env(user, server, timeout, SystemEnv)
+  }
+}
+
+impl<E: DrEnv> DrContext<E> {
+  pub fn try_new_with_env<A: ToSocketAddrs>(
+    user: User,
+    server: A,
+    timeout: Duration,
+    env: E,
+  ) -> AuthResult<Self> {
     let client = UdpSocket::bind("0.0.0.0:0")?;
-    let timeout = std::time::Duration::from_secs(timeout);
-    client.connect("10.100.61.3:61440")?;
+    client.connect(server)?;
     client.set_read_timeout(Some(timeout))?;
     client.set_write_timeout(Some(timeout))?;
-    let data = DrContextData::default();
+    let server = client.peer_addr()?;
 
-    Ok(Self { client, data, user })
+    Ok(Self {
+      client,
+      server,
+      packets: DrPackets::new(user, env),
+    })
   }
 }
 
-impl DrContext {
-  pub fn get_challenge_data(&self, try_times: u8, data: &mut [u8; 20]) {
+impl<E: DrEnv> Deref for DrContext<E> {
+  type Target = DrPackets<E>;
+
+  fn deref(&self) -> &DrPackets<E> {
+    &self.packets
+  }
+}
+
+impl<E: DrEnv> DerefMut for DrContext<E> {
+  fn deref_mut(&mut self) -> &mut DrPackets<E> {
+    &mut self.packets
+  }
+}
+
+impl<E: DrEnv> DrPackets<E> {
+  pub fn new(user: User, env: E) -> Self {
+    Self {
+      data: DrContextData::default(),
+      user,
+      env,
+    }
+  }
+
+  pub fn get_challenge_data(&mut self, try_times: u8, data: &mut [u8; 20]) {
     data[0] = 0x01;
     data[1] = 0x02 + try_times;
-    data[2] = rand::random();
-    data[3] = rand::random();
+    data[2] = self.env.random();
+    data[3] = self.env.random();
     data[4] = 0x6a;
   }
 
-  pub fn get_login_data(&mut self, data: &mut Vec<u8>) {
+  pub fn get_login_data(&mut self, data: &mut Vec<u8>) -> AuthResult<()> {
+    self.user.validate()?;
+    // whatever the caller passed, make room for the longest packet
+    data.clear();
+    data.resize(LOGIN_BUFFER_LEN, 0);
     let password_len = match self.user.password.len() {
       len if len > 16 => 16,
       len => len,
@@ -43,15 +106,18 @@ impl DrContext {
     data[0..3].copy_from_slice(&[0x03, 0x01, 0x00]);
     data[3] = self.user.username.len() as u8 + 20;
 
-    let md5a = md5::compute(
-      [0x03, 0x01]
-        .iter()
-        .chain(self.data.salt.iter())
-        .chain(self.user.password.as_bytes())
-        .copied()
-        .collect::<Vec<u8>>(),
+    let md5a = Zeroizing::new(
+      md5::compute(Zeroizing::new(
+        [0x03, 0x01]
+          .iter()
+          .chain(self.data.salt.iter())
+          .chain(self.user.password.as_bytes())
+          .copied()
+          .collect::<Vec<u8>>(),
+      ))
+      .0,
     );
-    self.data.md5a.copy_from_slice(&md5a.0);
+    self.data.md5a.copy_from_slice(md5a.as_ref());
     data[4..20].copy_from_slice(&self.data.md5a);
 
     let mut username_data = self.user.username.as_bytes().to_vec();
@@ -61,19 +127,22 @@ impl DrContext {
     data[56..58].copy_from_slice(&[0x20, 0x05]);
 
     for i in 0..6 {
-      data[58 + i] = md5a.0[i] ^ self.user.mac[i];
+      data[58 + i] = md5a[i] ^ self.user.mac[i];
     }
 
-    let md5b = md5::compute(
-      [0x01]
-        .iter()
-        .chain(self.user.password.as_bytes())
-        .chain(self.data.salt.iter())
-        .chain([0x00; 4].iter())
-        .copied()
-        .collect::<Vec<u8>>(),
+    let md5b = Zeroizing::new(
+      md5::compute(Zeroizing::new(
+        [0x01]
+          .iter()
+          .chain(self.user.password.as_bytes())
+          .chain(self.data.salt.iter())
+          .chain([0x00; 4].iter())
+          .copied()
+          .collect::<Vec<u8>>(),
+      ))
+      .0,
     );
-    data[64..80].copy_from_slice(&md5b.0);
+    data[64..80].copy_from_slice(md5b.as_ref());
 
     data[80] = 0x01;
 
@@ -91,7 +160,7 @@ impl DrContext {
 
     data[105] = 0x01;
 
-    let mut hostname_data = Self::get_host_name().as_bytes().to_vec();
+    let mut hostname_data = self.env.hostname().as_bytes().to_vec();
     hostname_data.resize(32, 0);
     data[110..142].copy_from_slice(&hostname_data);
 
@@ -115,7 +184,8 @@ impl DrContext {
     data[310] = 0x6a;
     data[313] = password_len as u8;
 
-    let ror_data = ror(md5a.as_slice(), self.user.password.as_bytes());
+    let ror_data =
+      Zeroizing::new(ror(md5a.as_slice(), self.user.password.as_bytes()));
 
     data[314..password_len + 314].copy_from_slice(&ror_data[0..password_len]);
     data[password_len + 314] = 0x02;
@@ -141,20 +211,21 @@ impl DrContext {
       data[password_len + 328 + i] = 0x00;
     }
 
-    data[password_len + 328 + zero_count] = rand::random();
-    data[password_len + 329 + zero_count] = rand::random();
+    data[password_len + 328 + zero_count] = self.env.random();
+    data[password_len + 329 + zero_count] = self.env.random();
 
     // let new_len = 334 + (password_len - 1) / 4 * 4;
     let new_len = 334 + password_len - 1;
     data.resize(new_len, 0);
+    Ok(())
   }
 
-  pub fn get_keep_alive_data_38(&self, data: &mut [u8; 38]) {
+  pub fn get_keep_alive_data_38(&mut self, data: &mut [u8; 38]) {
     data[0] = 0xff;
     data[1..17].copy_from_slice(&self.data.md5a);
     data[20..36].copy_from_slice(&self.data.tail);
-    data[36] = rand::random();
-    data[37] = rand::random();
+    data[36] = self.env.random();
+    data[37] = self.env.random();
   }
 
   pub fn get_keep_alive_data_40(
@@ -179,8 +250,8 @@ impl DrContext {
       data[6] = self.data.keep_alive_version.0;
       data[7] = self.data.keep_alive_version.1;
     }
-    data[8] = rand::random();
-    data[9] = rand::random();
+    data[8] = self.env.random();
+    data[9] = self.env.random();
     data[16..20].copy_from_slice(&self.data.tail_2);
     if let data::AliveType::SECOND = alive_type {
       let tmp = crc(
@@ -195,23 +266,50 @@ impl DrContext {
     }
   }
 
-  pub fn get_host_name() -> String {
-    match hostname::get() {
-      Ok(host) => host.to_string_lossy().to_string(),
-      Err(_) => "unknown".to_string(),
+  /// Builds the logout packet, which needs the salt of a fresh challenge and
+  /// the tail of the login reply.
+  pub fn get_logout_data(&self, data: &mut [u8; 80]) {
+    data[0..3].copy_from_slice(&[0x06, 0x01, 0x00]);
+    data[3] = self.user.username.len() as u8 + 20;
+
+    let md5 = Zeroizing::new(
+      md5::compute(Zeroizing::new(
+        [0x06, 0x01]
+          .iter()
+          .chain(self.data.salt.iter())
+          .chain(self.user.password.as_bytes())
+          .copied()
+          .collect::<Vec<u8>>(),
+      ))
+      .0,
+    );
+    data[4..20].copy_from_slice(md5.as_ref());
+
+    let mut username_data = self.user.username.as_bytes().to_vec();
+    username_data.resize(36, 0);
+    data[20..56].copy_from_slice(&username_data);
+
+    data[56..58].copy_from_slice(&[0x20, 0x05]);
+
+    for i in 0..6 {
+      data[58 + i] = md5[i] ^ self.user.mac[i];
     }
+
+    data[64..80].copy_from_slice(&self.data.tail);
   }
 }
 
+/// Obfuscates the password with the md5 in `data`, up to the shorter of
+/// the two.
 fn ror(data: &[u8], pwd: &[u8]) -> Vec<u8> {
-  let mut ret = Vec::new();
-  for i in 0..pwd.len() {
-    let x = data[i] ^ pwd[i];
-    ret.push((x << 3) + (x >> 5));
-  }
-  ret
+  data
+    .iter()
+    .zip(pwd)
+    .map(|(x, y)| (x ^ y).rotate_left(3))
+    .collect()
 }
 
+/// XORs `data` as big endian 4 byte words, then multiplies by 1968.
 fn checksum(data: &[u8]) -> [u8; 4] {
   let mut sum = [0u8; 4];
   let len = data.len();
@@ -223,28 +321,21 @@ fn checksum(data: &[u8]) -> [u8; 4] {
     sum[3] ^= data[i];
     i += 4;
   }
-  if i < len {
-    let mut tmp = [0u8; 4];
-    for j in (0..4).rev() {
-      tmp[j] = data[i];
-      i += 1;
-    }
-    for j in 0..4 {
-      sum[j] ^= tmp[j];
-    }
+  // a tail shorter than 4 bytes counts as if padded with zeros
+  for (j, byte) in data[i..].iter().enumerate() {
+    sum[3 - j] ^= byte;
   }
   let mut big_integer = u32::from_le_bytes(sum) as u64;
   big_integer *= 1968;
   let bytes = big_integer.to_le_bytes();
-  let mut i = 0;
   let mut ret = [0u8; 4];
-  for j in (0..4).rev() {
-    ret[j] = bytes[i];
-    i += 1;
+  for (j, byte) in bytes.iter().take(4).enumerate() {
+    ret[3 - j] = *byte;
   }
   ret
 }
 
+/// XORs `data` as little endian 2 byte words, ignoring an odd last byte.
 fn crc(data: &[u8]) -> [u8; 4] {
   let mut sum: u32 = 0;
   let len = data.len();
@@ -265,3 +356,293 @@ fn crc(data: &[u8]) -> [u8; 4] {
 
   result
 }
+
+#[cfg(test)]
+mod tests {
+  use proptest::{collection::vec, prelude::*};
+
+  use super::*;
+
+  // The expected packets and hashes are regression snapshots of this code,
+  // taken with fixed inputs. They catch unintended changes, not protocol
+  // bugs, and are no substitute for captures of the official client.
+  //
+  // There are no reference vectors yet: none of the challenge, login, 38 and
+  // 40 byte keep alive or logout packets has been checked against
+  // drcom-generic output or a capture. Vectors from either belong here, with
+  // their inputs and a note of where they come from.
+
+  struct FixedEnv {
+    random: Vec<u8>,
+    pos: usize,
+    hostname: String,
+  }
+
+  impl FixedEnv {
+    fn new(random: &[u8], hostname: &str) -> Self {
+      Self {
+        random: random.to_vec(),
+        pos: 0,
+        hostname: hostname.to_string(),
+      }
+    }
+  }
+
+  impl DrEnv for FixedEnv {
+    fn random(&mut self) -> u8 {
+      let byte = self.random[self.pos % self.random.len()];
+      self.pos += 1;
+      byte
+    }
+
+    fn hostname(&self) -> String {
+      self.hostname.clone()
+    }
+
+    fn sleep(&self, _duration: std::time::Duration) {}
+  }
+
+  const MAC: [u8; 6] = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+
+  fn context(username: &str, password: &str) -> DrPackets<FixedEnv> {
+    let user = User::new(username.to_string(), password.into(), MAC).unwrap();
+    DrPackets::new(user, FixedEnv::new(&[0xaa, 0xbb], "cygnus"))
+  }
+
+  fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+      .step_by(2)
+      .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+      .collect()
+  }
+
+  #[test]
+  fn test_checksum() {
+    let data = [0x01, 0x26, 0x07, 0x11, 0x00, 0x00]
+      .iter()
+      .chain(MAC.iter())
+      .copied()
+      .collect::<Vec<u8>>();
+    assert_eq!(checksum(&data).to_vec(), hex("b3759d70"));
+    assert_eq!(
+      checksum(&[1, 2, 3, 4, 5, 6, 7, 8]).to_vec(),
+      hex("dedf1c40")
+    );
+    assert_eq!(checksum(&[]), [0; 4]);
+  }
+
+  #[test]
+  fn test_crc() {
+    let data = (0..28).collect::<Vec<u8>>();
+    assert_eq!(crc(&data).to_vec(), hex("02020000"));
+    assert_eq!(crc(&[1, 2, 3, 4, 5]).to_vec(), hex("02060000"));
+  }
+
+  #[test]
+  fn test_ror() {
+    let md5 = (0..16).collect::<Vec<u8>>();
+    assert_eq!(ror(&md5, b"password"), hex("83038b839b53a31b"));
+    assert_eq!(ror(&md5, &[0; 20]).len(), 16);
+  }
+
+  proptest! {
+    #[test]
+    fn test_checksum_pads_tail(data in vec(any::<u8>(), 0..64)) {
+      let mut padded = data.clone();
+      padded.resize(data.len().div_ceil(4) * 4, 0);
+      prop_assert_eq!(checksum(&data), checksum(&padded));
+    }
+
+    #[test]
+    fn test_checksum_cancels_repeated_words(
+      data in vec(any::<u8>(), 0..64),
+      word in any::<[u8; 4]>(),
+    ) {
+      let mut padded = data.clone();
+      padded.resize(data.len().div_ceil(4) * 4, 0);
+      let repeated = [padded.as_slice(), &word, &word].concat();
+      prop_assert_eq!(checksum(&data), checksum(&repeated));
+    }
+
+    #[test]
+    fn test_crc_ignores_odd_tail(data in vec(any::<u8>(), 0..64), last: u8) {
+      let even = &data[..data.len() / 2 * 2];
+      let odd = [even, &[last]].concat();
+      prop_assert_eq!(crc(even), crc(&odd));
+      prop_assert_eq!(&crc(even)[2..], &[0, 0]);
+    }
+
+    #[test]
+    fn test_ror_takes_shorter_length(
+      md5 in vec(any::<u8>(), 0..20),
+      pwd in vec(any::<u8>(), 0..40),
+    ) {
+      let ret = ror(&md5, &pwd);
+      prop_assert_eq!(ret.len(), md5.len().min(pwd.len()));
+      for (i, x) in ret.iter().enumerate() {
+        prop_assert_eq!(x.rotate_right(3), md5[i] ^ pwd[i]);
+      }
+    }
+
+    #[test]
+    fn test_login_data_any_user(
+      username in "[ -~]{1,36}",
+      password in "[ -~]{1,16}",
+      len in 0..500usize,
+    ) {
+      let mut ctx = context("user", "password");
+      ctx.user.username = username;
+      ctx.user.password = password.as_str().into();
+      let mut data = vec![0xff; len];
+      ctx.get_login_data(&mut data).unwrap();
+      prop_assert_eq!(data.len(), 334 + password.len() - 1);
+    }
+  }
+
+  #[test]
+  fn test_challenge_data() {
+    let mut ctx = context("user", "password");
+    let mut data = [0; 20];
+    ctx.get_challenge_data(1, &mut data);
+    let mut expected = [0; 20];
+    expected[..5].copy_from_slice(&[0x01, 0x03, 0xaa, 0xbb, 0x6a]);
+    assert_eq!(data, expected);
+  }
+
+  #[test]
+  fn test_login_data() {
+    let mut ctx = context("user", "password");
+    ctx.data.salt = [0x01, 0x02, 0x03, 0x04];
+    ctx.data.client_ip = [10, 0, 0, 1];
+    let mut data = vec![0; 400];
+    ctx.get_login_data(&mut data).unwrap();
+    let expected = hex(concat!(
+      "03010018aeaf90d6a8ee436a809931ac5e66b1de757365720000000000000000",
+      "0000000000000000000000000000000000000000000000002005aebeb2e5ecbb",
+      "a950f249d73b6aadacf20e1bcb1d5299010a0000010000000000000000000000",
+      "00c608fc9976014e2901000000006379676e7573000000000000000000000000",
+      "00000000000000000000000000000a0a0a0a0a0a0a0a0a0a0a0a000000000000",
+      "0000940000000600000002000000f0230000020000004472434f4d00cf076a00",
+      "0000000000000000000000000000000000000000000000000000000000000000",
+      "0000000000000000000000000000000000000000000031633231306339393538",
+      "3566643232616430336433356339353639313161656563316562343439620000",
+      "000000000000000000000000000000000000000000006a000008f6761f2dfe0c",
+      "8970020cb3759d700000001122334455aabb000000",
+    ));
+    assert_eq!(data, expected);
+    assert_eq!(
+      ctx.data.md5a.to_vec(),
+      hex("aeaf90d6a8ee436a809931ac5e66b1de")
+    );
+  }
+
+  #[test]
+  fn test_login_data_padding() {
+    let mut ctx = context("20240001", "Secret#2024ab");
+    ctx.env = FixedEnv::new(&[0x01, 0x02], "lab-pc");
+    ctx.data.salt = [0xde, 0xad, 0xbe, 0xef];
+    ctx.data.client_ip = [49, 140, 1, 2];
+    let mut data = vec![0; 400];
+    ctx.get_login_data(&mut data).unwrap();
+    let expected = hex(concat!(
+      "0301001cb7cc12053fa785a5d31b0e8552594a58323032343030303100000000",
+      "0000000000000000000000000000000000000000000000002005b7dd30367bf2",
+      "e027496294c07006a756c321fb96387a01318c01020000000000000000000000",
+      "007e4d082872f375ab01000000006c61622d7063000000000000000000000000",
+      "00000000000000000000000000000a0a0a0a0a0a0a0a0a0a0a0a000000000000",
+      "0000940000000600000002000000f0230000020000004472434f4d00cf076a00",
+      "0000000000000000000000000000000000000000000000000000000000000000",
+      "0000000000000000000000000000000000000000000031633231306339393538",
+      "3566643232616430336433356339353639313161656563316562343439620000",
+      "000000000000000000000000000000000000000000006a00000d274d8bbbd29e",
+      "35bc1f49d12781020cb3759d7000000011223344550000000102",
+    ));
+    assert_eq!(data, expected);
+  }
+
+  #[test]
+  fn test_login_data_rejects_invalid_user() {
+    let mut ctx = context("user", "password");
+    ctx.user.username = "u".repeat(40);
+    let mut data = vec![0; 400];
+    assert!(ctx.get_login_data(&mut data).is_err());
+    assert_eq!(data, vec![0; 400]);
+  }
+
+  #[test]
+  fn test_logout_data() {
+    let mut ctx = context("user", "password");
+    ctx.data.salt = [0x01, 0x02, 0x03, 0x04];
+    ctx.data.tail = [0x5a; 16];
+    let mut data = [0; 80];
+    ctx.get_logout_data(&mut data);
+
+    let md5 = md5::compute(b"\x06\x01\x01\x02\x03\x04password").0;
+    assert_eq!(data[0..4], [0x06, 0x01, 0x00, 24]);
+    assert_eq!(data[4..20], md5);
+    assert_eq!(&data[20..24], b"user");
+    assert_eq!(data[24..56], [0; 32]);
+    assert_eq!(data[56..58], [0x20, 0x05]);
+    for i in 0..6 {
+      assert_eq!(data[58 + i], md5[i] ^ MAC[i]);
+    }
+    assert_eq!(data[64..80], [0x5a; 16]);
+  }
+
+  #[test]
+  fn test_keep_alive_data_38() {
+    let mut ctx = context("user", "password");
+    ctx
+      .data
+      .md5a
+      .copy_from_slice(&hex("aeaf90d6a8ee436a809931ac5e66b1de"));
+    ctx.data.tail = core::array::from_fn(|i| 0x10 + i as u8);
+    let mut data = [0; 38];
+    ctx.get_keep_alive_data_38(&mut data);
+    assert_eq!(
+      data.to_vec(),
+      hex(concat!(
+        "ffaeaf90d6a8ee436a809931ac5e66b1de000000101112131415161718191a1b",
+        "1c1d1e1faabb",
+      ))
+    );
+  }
+
+  #[test]
+  fn test_keep_alive_data_40() {
+    let mut ctx = context("user", "password");
+    ctx.data.client_ip = [10, 0, 0, 1];
+    ctx.data.keep_alive_version = (0xdc, 0x02);
+    let mut data = [0; 40];
+
+    ctx.get_keep_alive_data_40(AliveType::EXTRA, 0, &mut data);
+    assert_eq!(
+      data.to_vec(),
+      hex(concat!(
+        "070020000b010f27aabb00000000000000000000000000000000000000000000",
+        "0000000000000000",
+      ))
+    );
+
+    ctx.data.tail_2 = [0x01, 0x02, 0x03, 0x04];
+    let mut data = [0; 40];
+    ctx.get_keep_alive_data_40(AliveType::FIRST, 1, &mut data);
+    assert_eq!(
+      data.to_vec(),
+      hex(concat!(
+        "070120000b01dc02aabb00000000000001020304000000000000000000000000",
+        "0000000000000000",
+      ))
+    );
+
+    let mut data = [0; 40];
+    ctx.get_keep_alive_data_40(AliveType::SECOND, 2, &mut data);
+    assert_eq!(
+      data.to_vec(),
+      hex(concat!(
+        "070220000b03dc02aabb000000000000010203040000000052bf00000a000001",
+        "0000000000000000",
+      ))
+    );
+  }
+}
@@ -0,0 +1,250 @@
+//! Lets another thread watch and steer [`DrClient::run`](super::DrClient::run).
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use super::account::AccountInfo;
+use super::session::Event;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+  Connecting,
+  Challenged,
+  Online,
+  /// The session failed, a new one starts soon.
+  Offline,
+  /// Offline by the schedule or the budget.
+  Idle,
+  /// Logged out by [`Command::Logout`], waiting for [`Command::Login`].
+  LoggedOut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+  /// Log in again after a [`Logout`](Self::Logout).
+  Login,
+  /// Log out and stay offline until [`Login`](Self::Login).
+  Logout,
+  /// End the session and start a new one right away.
+  Reconnect,
+  /// Log out and return from [`DrClient::run`](super::DrClient::run).
+  Stop,
+}
+
+/// A snapshot of the client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Status {
+  pub state: State,
+  pub client_ip: Option<Ipv4Addr>,
+  /// Seconds since the current session went online.
+  pub uptime: Option<u64>,
+  pub rounds: u64,
+  pub last_error: Option<String>,
+  /// The address of the server of the current or last session.
+  pub server: Option<SocketAddr>,
+  /// Where the credentials in use were read from.
+  pub account: Option<String>,
+  /// The usage reported by the last accepted login.
+  pub usage: Option<AccountInfo>,
+}
+
+#[derive(Debug)]
+struct Shared {
+  state: State,
+  client_ip: Option<Ipv4Addr>,
+  online_since: Option<Instant>,
+  rounds: u64,
+  last_error: Option<String>,
+  server: Option<SocketAddr>,
+  account: Option<String>,
+  usage: Option<AccountInfo>,
+  command: Option<Command>,
+  logged_out: bool,
+  stopped: bool,
+}
+
+/// A handle shared between the client loop and whoever controls it. Clones
+/// refer to the same client.
+#[derive(Debug, Clone)]
+pub struct Controller {
+  inner: Arc<(Mutex<Shared>, Condvar)>,
+}
+
+impl Default for Controller {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Controller {
+  pub fn new() -> Self {
+    let shared = Shared {
+      state: State::Connecting,
+      client_ip: None,
+      online_since: None,
+      rounds: 0,
+      last_error: None,
+      server: None,
+      account: None,
+      usage: None,
+      command: None,
+      logged_out: false,
+      stopped: false,
+    };
+    Self {
+      inner: Arc::new((Mutex::new(shared), Condvar::new())),
+    }
+  }
+
+  fn lock(&self) -> MutexGuard<'_, Shared> {
+    self.inner.0.lock().unwrap_or_else(|e| e.into_inner())
+  }
+
+  pub fn status(&self) -> Status {
+    let shared = self.lock();
+    Status {
+      state: shared.state,
+      client_ip: shared.client_ip,
+      uptime: shared.online_since.map(|since| since.elapsed().as_secs()),
+      rounds: shared.rounds,
+      last_error: shared.last_error.clone(),
+      server: shared.server,
+      account: shared.account.clone(),
+      usage: shared.usage,
+    }
+  }
+
+  /// Asks the client loop to act, waking it up if it is waiting.
+  pub fn send(&self, command: Command) {
+    let mut shared = self.lock();
+    match command {
+      Command::Login => shared.logged_out = false,
+      Command::Logout => shared.logged_out = true,
+      Command::Reconnect => {}
+      Command::Stop => shared.stopped = true,
+    }
+    shared.command = Some(command);
+    self.inner.1.notify_all();
+  }
+
+  pub(crate) fn set_state(&self, state: State) {
+    self.lock().state = state;
+  }
+
+  pub(crate) fn set_server(&self, server: SocketAddr) {
+    self.lock().server = Some(server);
+  }
+
+  /// Updates the status from an event of the client.
+  pub(crate) fn observe(&self, event: &Event) {
+    let mut shared = self.lock();
+    match event {
+      Event::Challenged { client_ip } => {
+        shared.state = State::Challenged;
+        shared.client_ip = Some(*client_ip);
+      }
+      Event::Online { .. } => {
+        shared.state = State::Online;
+        shared.online_since = Some(Instant::now());
+        shared.rounds = 0;
+      }
+      Event::Account { info } => shared.usage = Some(*info),
+      Event::KeepAlive { round } => shared.rounds = *round,
+      Event::Offline { error } => {
+        shared.state = State::Offline;
+        shared.online_since = None;
+        shared.last_error = Some(error.to_string());
+      }
+      Event::Idle { .. } => shared.state = State::Idle,
+      Event::UsingAccount { source, .. } => {
+        shared.account = Some(source.clone())
+      }
+      _ => {}
+    }
+  }
+
+  /// Waits up to `timeout` for a command and takes it.
+  pub(crate) fn wait(&self, timeout: Duration) -> Option<Command> {
+    let shared = self.lock();
+    let (mut shared, _) = self
+      .inner
+      .1
+      .wait_timeout_while(shared, timeout, |shared| shared.command.is_none())
+      .unwrap_or_else(|e| e.into_inner());
+    shared.command.take()
+  }
+
+  /// Whether a [`Command::Stop`] was sent, even if it was taken already.
+  pub(crate) fn stopped(&self) -> bool {
+    self.lock().stopped
+  }
+
+  /// Blocks while the client is logged out by a [`Command::Logout`], and not
+  /// stopped.
+  pub(crate) fn wait_login(&self) {
+    let mut shared = self.lock();
+    if shared.logged_out {
+      shared.state = State::LoggedOut;
+      shared.client_ip = None;
+    }
+    while shared.logged_out && !shared.stopped {
+      shared = self.inner.1.wait(shared).unwrap_or_else(|e| e.into_inner());
+    }
+    shared.command = None;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::auth::AuthError;
+
+  #[test]
+  fn test_controller() {
+    let controller = Controller::new();
+    let client_ip = Ipv4Addr::new(10, 0, 0, 7);
+    controller.observe(&Event::Challenged { client_ip });
+    controller.observe(&Event::Online { client_ip });
+    controller.observe(&Event::KeepAlive { round: 3 });
+    let status = controller.status();
+    assert_eq!(status.state, State::Online);
+    assert_eq!(status.client_ip, Some(client_ip));
+    assert_eq!((status.uptime, status.rounds), (Some(0), 3));
+
+    controller.observe(&Event::Offline {
+      error: AuthError::ChallengeMaxTriesExceeded,
+    });
+    let status = controller.status();
+    assert_eq!(status.state, State::Offline);
+    assert_eq!(status.uptime, None);
+    assert_eq!(
+      status.last_error.as_deref(),
+      Some("Challenge max tries exceeded")
+    );
+
+    assert_eq!(controller.wait(Duration::from_millis(1)), None);
+    let remote = controller.clone();
+    let handle = std::thread::spawn(move || {
+      remote.send(Command::Logout);
+      std::thread::sleep(Duration::from_millis(20));
+      remote.send(Command::Login);
+    });
+    assert_eq!(
+      controller.wait(Duration::from_secs(5)),
+      Some(Command::Logout)
+    );
+    controller.wait_login();
+    assert_eq!(controller.wait(Duration::ZERO), None);
+    handle.join().unwrap();
+
+    // a stop also ends the wait for a login
+    controller.send(Command::Logout);
+    controller.send(Command::Stop);
+    controller.wait_login();
+    assert!(controller.stopped());
+  }
+}
@@ -1,3 +1,5 @@
+use zeroize::Zeroize;
+
 #[derive(Default)]
 pub struct DrContextData {
   // runtime data
@@ -9,6 +11,15 @@ pub struct DrContextData {
   pub keep_alive_version: (u8, u8),
 }
 
+impl Drop for DrContextData {
+  fn drop(&mut self) {
+    // md5a is derived from the password and tail authenticates the session
+    self.md5a.zeroize();
+    self.tail.zeroize();
+    self.salt.zeroize();
+  }
+}
+
 pub enum AliveType {
   FIRST,
   SECOND,
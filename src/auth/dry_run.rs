@@ -0,0 +1,317 @@
+//! Builds the packets of a whole session and prints them as annotated hex
+//! dumps, without sending anything.
+
+use std::io::{self, Write};
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+use crate::user::{secret::SecretBuf, User};
+
+use super::{
+  context::DrPackets,
+  data::AliveType,
+  env::{DrEnv, SystemEnv},
+  error::{AuthError, AuthResult},
+  reply::{KeepAliveReply, LoginReply},
+};
+
+/// Inputs that normally come from the server.
+#[derive(Debug, Clone)]
+pub struct DryRunOptions {
+  pub salt: [u8; 4],
+  pub client_ip: Ipv4Addr,
+  /// A login success reply, for the tail used by keep alive and logout.
+  pub login_reply: Option<Vec<u8>>,
+  /// A keep alive reply, for the version and the second tail.
+  pub keep_alive_reply: Option<Vec<u8>>,
+  /// Seed of the random bytes, so runs can be compared.
+  pub seed: u64,
+}
+
+/// The real hostname with seeded random bytes.
+struct DryRunEnv(StdRng);
+
+impl DrEnv for DryRunEnv {
+  fn random(&mut self) -> u8 {
+    self.0.next_u32() as u8
+  }
+
+  fn hostname(&self) -> String {
+    SystemEnv.hostname()
+  }
+
+  fn sleep(&self, _duration: Duration) {}
+}
+
+type Field = (usize, usize, &'static str);
+
+/// Writes every packet of a session, from the challenge to the logout, to
+/// `out`.
+pub fn dry_run(
+  user: User,
+  options: &DryRunOptions,
+  out: &mut impl Write,
+) -> AuthResult<()> {
+  let login_reply = match options.login_reply.as_deref() {
+    Some(reply) => match LoginReply::parse(reply) {
+      Some(LoginReply::Success { tail, .. }) => Some(tail),
+      _ => return Err(AuthError::UnusableReply("login")),
+    },
+    None => None,
+  };
+  let keep_alive_reply = match options.keep_alive_reply.as_deref() {
+    Some(reply) => {
+      let parsed = KeepAliveReply::parse(reply)
+        .and_then(|reply| Some((reply.version()?, reply.tail()?)));
+      Some(parsed.ok_or(AuthError::UnusableReply("keep alive"))?)
+    }
+    None => None,
+  };
+
+  let env = DryRunEnv(StdRng::seed_from_u64(options.seed));
+  let mut ctx = DrPackets::new(user, env);
+
+  writeln!(
+    out,
+    "# Dry run, nothing is sent. The login and logout packets hold data \
+     derived from the password, do not share them."
+  )?;
+
+  let mut challenge = [0; 20];
+  ctx.get_challenge_data(0, &mut challenge);
+  dump(out, "challenge", &challenge, &CHALLENGE_FIELDS)?;
+
+  ctx.data.salt = options.salt;
+  ctx.data.client_ip = options.client_ip.octets();
+  let mut login = SecretBuf::new(400);
+  ctx.get_login_data(&mut login)?;
+  let password_len = ctx.user.password.len().min(16);
+  dump(out, "login", &login, &login_fields(password_len))?;
+
+  if let Some(tail) = login_reply {
+    ctx.data.tail = tail;
+  }
+  let mut keep_alive_38 = [0; 38];
+  ctx.get_keep_alive_data_38(&mut keep_alive_38);
+  dump(out, "keep alive 38", &keep_alive_38, &KEEP_ALIVE_38_FIELDS)?;
+
+  if let Some((version, _)) = keep_alive_reply {
+    ctx.data.keep_alive_version = version;
+  }
+  let mut keep_alive_40 = [0; 40];
+  ctx.get_keep_alive_data_40(AliveType::EXTRA, 0, &mut keep_alive_40);
+  dump(
+    out,
+    "keep alive 40 extra",
+    &keep_alive_40,
+    &KEEP_ALIVE_40_FIELDS,
+  )?;
+  ctx.get_keep_alive_data_40(AliveType::FIRST, 0, &mut keep_alive_40);
+  dump(
+    out,
+    "keep alive 40 first",
+    &keep_alive_40,
+    &KEEP_ALIVE_40_FIELDS,
+  )?;
+  if let Some((_, tail)) = keep_alive_reply {
+    ctx.data.tail_2 = tail;
+  }
+  ctx.get_keep_alive_data_40(AliveType::SECOND, 1, &mut keep_alive_40);
+  dump(
+    out,
+    "keep alive 40 second",
+    &keep_alive_40,
+    &KEEP_ALIVE_40_FIELDS,
+  )?;
+
+  let mut logout = [0; 80];
+  ctx.get_logout_data(&mut logout);
+  dump(out, "logout", &logout, &LOGOUT_FIELDS)?;
+  Ok(())
+}
+
+const CHALLENGE_FIELDS: [Field; 4] = [
+  (0, 1, "type"),
+  (1, 2, "try"),
+  (2, 4, "random"),
+  (4, 5, "version"),
+];
+
+const KEEP_ALIVE_38_FIELDS: [Field; 4] = [
+  (0, 1, "type"),
+  (1, 17, "md5a"),
+  (20, 36, "tail of the login reply"),
+  (36, 38, "random"),
+];
+
+const KEEP_ALIVE_40_FIELDS: [Field; 9] = [
+  (0, 1, "type"),
+  (1, 2, "counter"),
+  (2, 3, "length"),
+  (4, 5, "subtype"),
+  (5, 6, "step"),
+  (6, 8, "version"),
+  (8, 10, "random"),
+  (16, 20, "tail of the previous reply"),
+  (24, 32, "crc and client ip (second step)"),
+];
+
+const LOGOUT_FIELDS: [Field; 7] = [
+  (0, 3, "type"),
+  (3, 4, "username length + 20"),
+  (4, 20, "md5(06 01 salt password)"),
+  (20, 56, "username"),
+  (56, 58, "control check and adapter"),
+  (58, 64, "md5 ^ mac"),
+  (64, 80, "tail of the login reply"),
+];
+
+fn login_fields(password_len: usize) -> Vec<Field> {
+  let p = password_len;
+  let padding = (4 - p % 4) % 4;
+  vec![
+    (0, 3, "type"),
+    (3, 4, "username length + 20"),
+    (4, 20, "md5a = md5(03 01 salt password)"),
+    (20, 56, "username"),
+    (56, 58, "control check and adapter"),
+    (58, 64, "md5a ^ mac"),
+    (64, 80, "md5(01 password salt 00000000)"),
+    (80, 81, "ip count"),
+    (81, 85, "client ip"),
+    (97, 105, "md5 of the above"),
+    (105, 106, "ip dog"),
+    (110, 142, "hostname"),
+    (142, 154, "dns and dhcp servers"),
+    (162, 182, "os version"),
+    (182, 191, "DrCOM"),
+    (246, 286, "client hash"),
+    (310, 311, "auth version"),
+    (313, 314, "password length"),
+    (314, 314 + p, "ror(md5a, password)"),
+    (314 + p, 316 + p, "auth extra"),
+    (316 + p, 320 + p, "checksum"),
+    (322 + p, 328 + p, "mac"),
+    (328 + p + padding, 330 + p + padding, "random"),
+  ]
+}
+
+fn dump(
+  out: &mut impl Write,
+  name: &str,
+  packet: &[u8],
+  fields: &[Field],
+) -> io::Result<()> {
+  writeln!(out, "\n{} ({} bytes)", name, packet.len())?;
+  for (row, chunk) in packet.chunks(16).enumerate() {
+    let hex = chunk
+      .iter()
+      .map(|b| format!("{:02x}", b))
+      .collect::<Vec<_>>()
+      .join(" ");
+    let ascii = chunk
+      .iter()
+      .map(|&b| match b {
+        0x20..=0x7e => b as char,
+        _ => '.',
+      })
+      .collect::<String>();
+    writeln!(out, "  {:04x}  {:<47}  |{}|", row * 16, hex, ascii)?;
+  }
+  for &(start, end, label) in fields {
+    let Some(bytes) = packet.get(start..end).filter(|b| !b.is_empty()) else {
+      continue;
+    };
+    let hex = bytes
+      .iter()
+      .map(|b| format!("{:02x}", b))
+      .collect::<String>();
+    writeln!(out, "  [{:3}..{:3}] {:<40} {}", start, end, label, hex)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn output(options: &DryRunOptions) -> String {
+    let user =
+      User::new("user".into(), "password".into(), [0, 1, 2, 3, 4, 5]).unwrap();
+    let mut out = Vec::new();
+    dry_run(user, options, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn test_dry_run() {
+    let mut login_reply = vec![0x04; 23];
+    login_reply.extend_from_slice(&[0xab; 16]);
+    let mut keep_alive_reply = vec![0x07; 28];
+    keep_alive_reply.extend_from_slice(&[0xdc, 0x02]);
+    keep_alive_reply[16..20].copy_from_slice(&[9, 9, 9, 9]);
+    let options = DryRunOptions {
+      salt: [1, 2, 3, 4],
+      client_ip: Ipv4Addr::new(10, 0, 0, 7),
+      login_reply: Some(login_reply),
+      keep_alive_reply: Some(keep_alive_reply),
+      seed: 7,
+    };
+    let text = output(&options);
+    assert_eq!(text, output(&options), "the seed fixes the random bytes");
+
+    let packets = text
+      .lines()
+      .filter(|l| l.ends_with("bytes)"))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      packets,
+      [
+        "challenge (20 bytes)",
+        "login (341 bytes)",
+        "keep alive 38 (38 bytes)",
+        "keep alive 40 extra (40 bytes)",
+        "keep alive 40 first (40 bytes)",
+        "keep alive 40 second (40 bytes)",
+        "logout (80 bytes)",
+      ]
+    );
+    assert!(text.contains(&format!("client ip{} 0a000007", " ".repeat(31))));
+    assert!(text.contains(&"ab".repeat(16)));
+    // the second step carries the version and the tail of the first reply
+    assert!(text.contains("  0000  07 01 20 00 0b 03 dc 02"));
+    assert!(text.contains("09090909"));
+  }
+
+  #[test]
+  fn test_dry_run_rejects_unusable_replies() {
+    let user =
+      User::new("user".into(), "password".into(), [0, 1, 2, 3, 4, 5]).unwrap();
+    let options = DryRunOptions {
+      salt: [1, 2, 3, 4],
+      client_ip: Ipv4Addr::new(10, 0, 0, 7),
+      login_reply: None,
+      keep_alive_reply: None,
+      seed: 0,
+    };
+    let cases = [
+      (Some(vec![0x05; 39]), None, "login"),
+      (Some(vec![0x04; 38]), None, "login"),
+      (None, Some(vec![0x07; 29]), "keep alive"),
+      (None, Some(vec![0x04; 30]), "keep alive"),
+    ];
+    for (login_reply, keep_alive_reply, name) in cases {
+      let options = DryRunOptions {
+        login_reply,
+        keep_alive_reply,
+        ..options.clone()
+      };
+      let mut out = Vec::new();
+      let error = dry_run(user.clone(), &options, &mut out).unwrap_err();
+      assert!(matches!(error, AuthError::UnusableReply(n) if n == name));
+      assert!(out.is_empty());
+    }
+  }
+}
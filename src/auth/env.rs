@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+/// Provides the non-deterministic inputs of the protocol (random bytes and
+/// the hostname), so packets can be reproduced byte for byte, and the sleeps
+/// between packets, so tests need not wait.
+pub trait DrEnv {
+  fn random(&mut self) -> u8;
+  fn hostname(&self) -> String;
+  fn sleep(&self, duration: Duration);
+}
+
+/// The real environment, backed by the thread RNG and the OS.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemEnv;
+
+impl DrEnv for SystemEnv {
+  fn random(&mut self) -> u8 {
+    rand::random()
+  }
+
+  fn hostname(&self) -> String {
+    match hostname::get() {
+      Ok(host) => host.to_string_lossy().to_string(),
+      Err(_) => "unknown".to_string(),
+    }
+  }
+
+  fn sleep(&self, duration: Duration) {
+    std::thread::sleep(duration);
+  }
+}
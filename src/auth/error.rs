@@ -8,6 +8,12 @@ pub enum AuthError {
   #[error("User error -> {0}")]
   User(#[from] UserError),
 
+  #[error("Cannot resolve server {0} to an IPv4 address")]
+  UnresolvedServer(String),
+
+  #[error("Malformed {0} reply from the server")]
+  MalformedReply(&'static str),
+
   #[error("Challenge max tries exceeded")]
   ChallengeMaxTriesExceeded,
 
@@ -20,8 +26,90 @@ pub enum AuthError {
   #[error("Invalid username or password")]
   InvalidUsernameOrPassword,
 
+  #[error("Account in use elsewhere")]
+  AccountInUse,
+
+  #[error("Insufficient account balance")]
+  InsufficientBalance,
+
+  #[error("Account suspended")]
+  AccountSuspended,
+
+  #[error("Network changed, logging in again")]
+  NetworkChanged,
+
+  #[error("Logout rejected by the server")]
+  LogoutRejected,
+
+  #[error("Forced offline by the server: {0}")]
+  ForcedOffline(String),
+
+  #[error("Reconnect requested")]
+  ReconnectRequested,
+
+  #[error("Logout requested")]
+  LogoutRequested,
+
+  #[error("Stop requested")]
+  StopRequested,
+
+  #[error("Outside the online schedule")]
+  OutsideSchedule,
+
+  #[error("Online time budget exhausted")]
+  BudgetExhausted,
+
+  #[error("The {0} reply is not a complete success reply")]
+  UnusableReply(&'static str),
+
+  #[error("No accounts to run with")]
+  NoAccounts,
+
   #[error("Unknown error")]
   Unknown,
 }
 
+impl AuthError {
+  /// A stable name of the variant, for scripts to match on.
+  pub fn kind(&self) -> &'static str {
+    match self {
+      AuthError::Io(_) => "io",
+      AuthError::User(_) => "user",
+      AuthError::UnresolvedServer(_) => "unresolved_server",
+      AuthError::MalformedReply(_) => "malformed_reply",
+      AuthError::ChallengeMaxTriesExceeded => "challenge_max_tries_exceeded",
+      AuthError::AppMaxTriesExceeded => "app_max_tries_exceeded",
+      AuthError::InvalidMacAddress => "invalid_mac_address",
+      AuthError::InvalidUsernameOrPassword => "invalid_username_or_password",
+      AuthError::AccountInUse => "account_in_use",
+      AuthError::InsufficientBalance => "insufficient_balance",
+      AuthError::AccountSuspended => "account_suspended",
+      AuthError::NetworkChanged => "network_changed",
+      AuthError::LogoutRejected => "logout_rejected",
+      AuthError::ForcedOffline(_) => "forced_offline",
+      AuthError::ReconnectRequested => "reconnect_requested",
+      AuthError::LogoutRequested => "logout_requested",
+      AuthError::StopRequested => "stop_requested",
+      AuthError::OutsideSchedule => "outside_schedule",
+      AuthError::BudgetExhausted => "budget_exhausted",
+      AuthError::UnusableReply(_) => "unusable_reply",
+      AuthError::NoAccounts => "no_accounts",
+      AuthError::Unknown => "unknown",
+    }
+  }
+
+  /// Whether the server refused the credentials or the state of the account,
+  /// so another account may do better.
+  pub fn is_account_error(&self) -> bool {
+    matches!(
+      self,
+      AuthError::InvalidMacAddress
+        | AuthError::InvalidUsernameOrPassword
+        | AuthError::AccountInUse
+        | AuthError::InsufficientBalance
+        | AuthError::AccountSuspended
+    )
+  }
+}
+
 pub type AuthResult<T> = Result<T, AuthError>;
@@ -0,0 +1,66 @@
+use std::time::{Duration, Instant};
+
+/// Picks which of several accounts
+/// [`DrClient::run_accounts`](super::DrClient::run_accounts) logs in with. The
+/// first account that is not cooling down wins, so the
+/// client returns to the preferred account once its cooldown is over.
+pub(crate) struct Accounts {
+  cooldown: Duration,
+  cooling_until: Vec<Option<Instant>>,
+}
+
+impl Accounts {
+  pub fn new(count: usize, cooldown: Duration) -> Self {
+    Self {
+      cooldown,
+      cooling_until: vec![None; count],
+    }
+  }
+
+  /// The account to use at `now`, or how long to wait until one is usable.
+  pub fn select(&self, now: Instant) -> Result<usize, Duration> {
+    let ready = |until: &Option<Instant>| until.is_none_or(|t| t <= now);
+    if let Some(index) = self.cooling_until.iter().position(ready) {
+      return Ok(index);
+    }
+    let earliest = self.cooling_until.iter().flatten().min();
+    Err(earliest.map_or(Duration::ZERO, |t| t.duration_since(now)))
+  }
+
+  /// Puts `index` aside after the server refused it, unless it is the only
+  /// account and there is nothing to switch to.
+  pub fn reject(&mut self, index: usize, now: Instant) {
+    if self.cooling_until.len() > 1 {
+      self.cooling_until[index] = Some(now + self.cooldown);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_accounts() {
+    let cooldown = Duration::from_secs(60);
+    let now = Instant::now();
+    let mut accounts = Accounts::new(3, cooldown);
+    assert_eq!(accounts.select(now), Ok(0));
+
+    accounts.reject(0, now);
+    assert_eq!(accounts.select(now), Ok(1));
+    accounts.reject(1, now + Duration::from_secs(10));
+    assert_eq!(accounts.select(now + Duration::from_secs(10)), Ok(2));
+    accounts.reject(2, now + Duration::from_secs(20));
+    assert_eq!(
+      accounts.select(now + Duration::from_secs(20)),
+      Err(Duration::from_secs(40))
+    );
+    // the first account is preferred again once it cooled down
+    assert_eq!(accounts.select(now + cooldown), Ok(0));
+
+    let mut single = Accounts::new(1, cooldown);
+    single.reject(0, now);
+    assert_eq!(single.select(now), Ok(0));
+  }
+}
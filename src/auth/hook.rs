@@ -0,0 +1,246 @@
+//! Runs local commands when the session changes state, such as restarting a
+//! VPN after login.
+//!
+//! Each hook runs through `sh -c` on its own thread, so the auth loop never
+//! waits for it. It is told about the event by these variables:
+//!
+//! | Variable | Value |
+//! | --- | --- |
+//! | `CYGNUS_EVENT` | `login`, `logout`, `keepalive_fail` or `error` |
+//! | `CYGNUS_USERNAME` | The username of the session |
+//! | `CYGNUS_SERVER` | The server as configured, `host:port` |
+//! | `CYGNUS_CLIENT_IP` | The address assigned by the server, if known |
+//! | `CYGNUS_ERROR` | The error message, or why the client logged out |
+//! | `CYGNUS_ERROR_KIND` | [`AuthError::kind`] of that error or reason |
+//! | `CYGNUS_ATTEMPT` | Sessions started since the last login, from 1 |
+
+use std::net::Ipv4Addr;
+use std::process::{Child, Command, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info, warn};
+
+use super::error::AuthError;
+
+/// How often a running hook is checked for exit.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+  /// The login was accepted.
+  Login,
+  /// The client logged out, by the schedule, the budget or a command.
+  Logout,
+  /// An online session failed, usually in keep alive.
+  KeepAliveFail,
+  /// A session failed, online or not.
+  Error,
+}
+
+impl Hook {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Hook::Login => "login",
+      Hook::Logout => "logout",
+      Hook::KeepAliveFail => "keepalive_fail",
+      Hook::Error => "error",
+    }
+  }
+}
+
+/// What a hook is told about its event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HookContext {
+  pub username: String,
+  pub server: String,
+  pub client_ip: Option<Ipv4Addr>,
+  pub error: Option<String>,
+  pub error_kind: Option<&'static str>,
+  pub attempt: u64,
+}
+
+impl HookContext {
+  /// Describes `error` in the context.
+  pub fn with_error(mut self, error: &AuthError) -> Self {
+    self.error = Some(error.to_string());
+    self.error_kind = Some(error.kind());
+    self
+  }
+
+  fn vars(&self, hook: Hook) -> Vec<(&'static str, String)> {
+    let mut vars = vec![
+      ("CYGNUS_EVENT", hook.name().to_string()),
+      ("CYGNUS_USERNAME", self.username.clone()),
+      ("CYGNUS_SERVER", self.server.clone()),
+      ("CYGNUS_ATTEMPT", self.attempt.to_string()),
+    ];
+    if let Some(client_ip) = self.client_ip {
+      vars.push(("CYGNUS_CLIENT_IP", client_ip.to_string()));
+    }
+    if let Some(error) = &self.error {
+      vars.push(("CYGNUS_ERROR", error.clone()));
+    }
+    if let Some(kind) = self.error_kind {
+      vars.push(("CYGNUS_ERROR_KIND", kind.to_string()));
+    }
+    vars
+  }
+}
+
+/// The commands to run, each optional.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hooks {
+  pub on_login: Option<String>,
+  pub on_logout: Option<String>,
+  pub on_keepalive_fail: Option<String>,
+  pub on_error: Option<String>,
+  /// Hooks still running after this long are killed.
+  pub timeout: Duration,
+}
+
+impl Default for Hooks {
+  fn default() -> Self {
+    Self {
+      on_login: None,
+      on_logout: None,
+      on_keepalive_fail: None,
+      on_error: None,
+      timeout: Duration::from_secs(30),
+    }
+  }
+}
+
+impl Hooks {
+  fn command(&self, hook: Hook) -> Option<&str> {
+    match hook {
+      Hook::Login => self.on_login.as_deref(),
+      Hook::Logout => self.on_logout.as_deref(),
+      Hook::KeepAliveFail => self.on_keepalive_fail.as_deref(),
+      Hook::Error => self.on_error.as_deref(),
+    }
+  }
+
+  /// Starts the command of `hook` in the background, if there is one.
+  /// Joining the returned thread waits for the command to exit or be killed.
+  pub fn fire(
+    &self,
+    hook: Hook,
+    context: &HookContext,
+  ) -> Option<JoinHandle<()>> {
+    let mut command = Command::new("sh");
+    command
+      .arg("-c")
+      .arg(self.command(hook)?)
+      .envs(context.vars(hook))
+      .stdin(Stdio::null());
+    // a group of its own, so a timeout kills whatever the hook started
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = match command.spawn() {
+      Ok(child) => child,
+      Err(e) => {
+        warn!(hook = hook.name(), "Failed to start hook: {}", e);
+        return None;
+      }
+    };
+    info!(hook = hook.name(), pid = child.id(), "Hook started");
+
+    let timeout = self.timeout;
+    let handle = std::thread::spawn(move || {
+      let deadline = Instant::now() + timeout;
+      loop {
+        match child.try_wait() {
+          Ok(Some(status)) if status.success() => {
+            debug!(hook = hook.name(), "Hook finished");
+            return;
+          }
+          Ok(Some(status)) => {
+            warn!(hook = hook.name(), "Hook failed: {}", status);
+            return;
+          }
+          Ok(None) if Instant::now() >= deadline => {
+            warn!(
+              hook = hook.name(),
+              "Hook timed out after {} seconds, killing it",
+              timeout.as_secs_f32()
+            );
+            kill(&mut child);
+            let _ = child.wait();
+            return;
+          }
+          Ok(None) => std::thread::sleep(POLL_INTERVAL),
+          Err(e) => {
+            warn!(hook = hook.name(), "Failed to wait for hook: {}", e);
+            return;
+          }
+        }
+      }
+    });
+    Some(handle)
+  }
+}
+
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+  // SAFETY: kill has no memory safety preconditions; the group was created
+  // for the child and it has not been reaped yet
+  unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut Child) {
+  let _ = child.kill();
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use crate::testing::temp_path;
+
+  #[test]
+  fn test_fire_hook() {
+    let path = temp_path("hook.env");
+    let hooks = Hooks {
+      on_error: Some(format!(
+        "echo \"$CYGNUS_EVENT $CYGNUS_USERNAME $CYGNUS_CLIENT_IP \
+         $CYGNUS_ERROR_KIND $CYGNUS_ATTEMPT\" > {}",
+        path.display()
+      )),
+      ..Hooks::default()
+    };
+    let context = HookContext {
+      username: "user".to_string(),
+      server: "127.0.0.1:61440".to_string(),
+      client_ip: Some(Ipv4Addr::new(10, 0, 0, 7)),
+      attempt: 2,
+      ..HookContext::default()
+    }
+    .with_error(&AuthError::ChallengeMaxTriesExceeded);
+
+    assert!(hooks.fire(Hook::Login, &context).is_none());
+    hooks.fire(Hook::Error, &context).unwrap().join().unwrap();
+    assert_eq!(
+      std::fs::read_to_string(&path).unwrap(),
+      "error user 10.0.0.7 challenge_max_tries_exceeded 2\n"
+    );
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn test_hook_timeout() {
+    let hooks = Hooks {
+      on_logout: Some("sleep 10".to_string()),
+      timeout: Duration::from_millis(100),
+      ..Hooks::default()
+    };
+    let start = Instant::now();
+    let handle = hooks.fire(Hook::Logout, &HookContext::default()).unwrap();
+    assert!(
+      start.elapsed() < Duration::from_secs(1),
+      "fire must not block"
+    );
+    handle.join().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+  }
+}
@@ -0,0 +1,410 @@
+//! Watches an interface with rtnetlink, so a session reacts to link and
+//! address changes right away instead of after several `recv` timeouts.
+
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info, warn};
+
+use super::error::{AuthError, AuthResult};
+
+const NLMSG_HDRLEN: usize = 16;
+const IFINFOMSG_LEN: usize = 16;
+const IFADDRMSG_LEN: usize = 8;
+
+/// How often a wait for the link asks whether to give up.
+const INTERRUPT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkEvent {
+  Up,
+  Down,
+  /// An IPv4 address was added to or removed from the interface.
+  AddressChanged,
+}
+
+pub struct LinkWatcher {
+  interface: String,
+  index: u32,
+  socket: OwnedFd,
+  up: bool,
+  pending: VecDeque<LinkEvent>,
+}
+
+impl LinkWatcher {
+  pub fn new(interface: &str) -> io::Result<Self> {
+    CString::new(interface)
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let index = index_of(interface);
+    if index == 0 {
+      return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: plain socket call, the result is checked below
+    let fd = unsafe {
+      libc::socket(
+        libc::AF_NETLINK,
+        libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+        libc::NETLINK_ROUTE,
+      )
+    };
+    if fd < 0 {
+      return Err(io::Error::last_os_error());
+    }
+    // SAFETY: fd is a freshly created socket owned by nothing else
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // SAFETY: sockaddr_nl is plain data, all zero is a valid value
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = (libc::RTMGRP_LINK | libc::RTMGRP_IPV4_IFADDR) as u32;
+    // SAFETY: addr is a valid sockaddr_nl of the given size
+    let ret = unsafe {
+      libc::bind(
+        socket.as_raw_fd(),
+        &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+        std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+      )
+    };
+    if ret < 0 {
+      return Err(io::Error::last_os_error());
+    }
+
+    let up = operstate_up(interface);
+    info!(interface, index, up, "Watching interface");
+    Ok(Self {
+      interface: interface.to_string(),
+      index,
+      socket,
+      up,
+      pending: VecDeque::new(),
+    })
+  }
+
+  pub fn interface(&self) -> &str {
+    &self.interface
+  }
+
+  pub fn is_up(&self) -> bool {
+    self.up
+  }
+
+  /// Waits up to `timeout` (forever if `None`) for the next change of the
+  /// interface.
+  pub fn next_event(
+    &mut self,
+    timeout: Option<Duration>,
+  ) -> io::Result<Option<LinkEvent>> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+      if let Some(event) = self.pending.pop_front() {
+        return Ok(Some(event));
+      }
+      let wait = match deadline {
+        Some(deadline) => {
+          match deadline.checked_duration_since(Instant::now()) {
+            Some(left) => left.as_millis().min(i32::MAX as u128) as i32,
+            None => return Ok(None),
+          }
+        }
+        None => -1,
+      };
+      let mut fds = libc::pollfd {
+        fd: self.socket.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+      };
+      // SAFETY: fds is a valid pollfd array of length 1
+      match unsafe { libc::poll(&mut fds, 1, wait) } {
+        0 => return Ok(None),
+        n if n < 0 => {
+          let e = io::Error::last_os_error();
+          if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+          }
+        }
+        _ => self.receive(),
+      }
+    }
+  }
+
+  /// Blocks until the link is up, or `interrupt` fails. It is called every
+  /// second while the link is down.
+  pub fn wait_up(
+    &mut self,
+    mut interrupt: impl FnMut() -> AuthResult<()>,
+  ) -> AuthResult<()> {
+    while !self.up {
+      interrupt()?;
+      self.next_event(Some(INTERRUPT_CHECK_INTERVAL))?;
+    }
+    self.pending.clear();
+    Ok(())
+  }
+
+  /// Waits between keep alive rounds. A link change ends the session with
+  /// [`AuthError::NetworkChanged`], after the link is back up, unless
+  /// `interrupt` fails first, see [`wait_up`](Self::wait_up).
+  pub fn pause(
+    &mut self,
+    interval: Duration,
+    interrupt: impl FnMut() -> AuthResult<()>,
+  ) -> AuthResult<()> {
+    match self.next_event(Some(interval))? {
+      None => Ok(()),
+      Some(LinkEvent::Down) => {
+        warn!(interface = self.interface, "Link down, pausing keep alive");
+        self.wait_up(interrupt)?;
+        info!(interface = self.interface, "Link up");
+        Err(AuthError::NetworkChanged)
+      }
+      Some(event) => {
+        info!(interface = self.interface, ?event, "Network changed");
+        Err(AuthError::NetworkChanged)
+      }
+    }
+  }
+
+  /// Reads the pending messages. A failure is logged and the state read
+  /// again, so the watcher keeps working.
+  fn receive(&mut self) {
+    let mut buf = [0u8; 8192];
+    // SAFETY: buf is valid for writes of its length
+    let len = unsafe {
+      libc::recv(
+        self.socket.as_raw_fd(),
+        buf.as_mut_ptr() as *mut libc::c_void,
+        buf.len(),
+        libc::MSG_DONTWAIT,
+      )
+    };
+    if len < 0 {
+      let e = io::Error::last_os_error();
+      if !matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+      ) {
+        // such as ENOBUFS when the kernel dropped messages
+        warn!(interface = self.interface, "Netlink receive failed: {}", e);
+        self.resync();
+      }
+      return;
+    }
+    let interface = &self.interface;
+    let events = parse_messages(
+      &buf[..len as usize],
+      &mut self.index,
+      &mut self.up,
+      || index_of(interface),
+    );
+    debug!(?events, "Netlink messages received");
+    self.pending.extend(events);
+  }
+
+  /// Reads the state of the link again after messages were lost, queueing
+  /// the change if there is one.
+  fn resync(&mut self) {
+    let index = index_of(&self.interface);
+    if index != 0 {
+      self.index = index;
+    }
+    let up = index != 0 && operstate_up(&self.interface);
+    info!(interface = self.interface, index, up, "Link state resynced");
+    if up != self.up {
+      self.up = up;
+      self
+        .pending
+        .push_back(if up { LinkEvent::Up } else { LinkEvent::Down });
+    }
+  }
+}
+
+/// The index of `interface`, 0 if there is no such interface.
+fn index_of(interface: &str) -> u32 {
+  let Ok(name) = CString::new(interface) else {
+    return 0;
+  };
+  // SAFETY: name is a valid NUL-terminated string
+  unsafe { libc::if_nametoindex(name.as_ptr()) }
+}
+
+/// Reads `/sys/class/net/<interface>/operstate`, treating interfaces
+/// without carrier detection (`unknown`) as up.
+fn operstate_up(interface: &str) -> bool {
+  let path = format!("/sys/class/net/{}/operstate", interface);
+  match std::fs::read_to_string(path) {
+    Ok(state) => matches!(state.trim(), "up" | "unknown"),
+    Err(_) => true,
+  }
+}
+
+/// Turns a buffer of rtnetlink messages into the changes of interface
+/// `index`, tracking whether its link is up in `up`. A new link of another
+/// index may be the interface created again (a replugged USB or PPP
+/// interface), so `resolve` looks its index up by name then.
+fn parse_messages(
+  buf: &[u8],
+  index: &mut u32,
+  up: &mut bool,
+  mut resolve: impl FnMut() -> u32,
+) -> Vec<LinkEvent> {
+  let mut events = Vec::new();
+  let mut offset = 0;
+  while offset + NLMSG_HDRLEN <= buf.len() {
+    let header = &buf[offset..];
+    let len = u32::from_ne_bytes(header[0..4].try_into().unwrap()) as usize;
+    let kind = u16::from_ne_bytes(header[4..6].try_into().unwrap());
+    if len < NLMSG_HDRLEN || offset + len > buf.len() {
+      break;
+    }
+    let payload = &header[NLMSG_HDRLEN..len];
+
+    match kind {
+      libc::RTM_NEWLINK | libc::RTM_DELLINK
+        if payload.len() >= IFINFOMSG_LEN =>
+      {
+        let msg_index = i32::from_ne_bytes(payload[4..8].try_into().unwrap());
+        let flags = u32::from_ne_bytes(payload[8..12].try_into().unwrap());
+        if kind == libc::RTM_NEWLINK
+          && msg_index as u32 != *index
+          && resolve() == msg_index as u32
+        {
+          info!(index = msg_index, "Interface reappeared with a new index");
+          *index = msg_index as u32;
+        }
+        if msg_index as u32 == *index {
+          let now_up = kind == libc::RTM_NEWLINK
+            && flags & libc::IFF_UP as u32 != 0
+            && flags & libc::IFF_LOWER_UP as u32 != 0;
+          if now_up != *up {
+            *up = now_up;
+            events.push(if now_up {
+              LinkEvent::Up
+            } else {
+              LinkEvent::Down
+            });
+          }
+        }
+      }
+      libc::RTM_NEWADDR | libc::RTM_DELADDR
+        if payload.len() >= IFADDRMSG_LEN =>
+      {
+        let family = payload[0];
+        let msg_index = u32::from_ne_bytes(payload[4..8].try_into().unwrap());
+        if msg_index == *index && family == libc::AF_INET as u8 {
+          events.push(LinkEvent::AddressChanged);
+        }
+      }
+      _ => {}
+    }
+    // messages are aligned to 4 bytes
+    offset += (len + 3) & !3;
+  }
+  events
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn message(kind: u16, payload: &[u8]) -> Vec<u8> {
+    let len = (NLMSG_HDRLEN + payload.len()) as u32;
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&len.to_ne_bytes());
+    msg.extend_from_slice(&kind.to_ne_bytes());
+    msg.extend_from_slice(&[0; 10]);
+    msg.extend_from_slice(payload);
+    msg.resize((msg.len() + 3) & !3, 0);
+    msg
+  }
+
+  fn link(kind: u16, index: i32, flags: u32) -> Vec<u8> {
+    let mut payload = vec![0u8; 4];
+    payload.extend_from_slice(&index.to_ne_bytes());
+    payload.extend_from_slice(&flags.to_ne_bytes());
+    payload.extend_from_slice(&0u32.to_ne_bytes());
+    message(kind, &payload)
+  }
+
+  fn addr(kind: u16, family: u8, index: u32) -> Vec<u8> {
+    let mut payload = vec![family, 24, 0, 0];
+    payload.extend_from_slice(&index.to_ne_bytes());
+    // an unaligned attribute-free payload, to check alignment handling
+    payload.push(0);
+    message(kind, &payload)
+  }
+
+  #[test]
+  fn test_parse_messages() {
+    let running = (libc::IFF_UP | libc::IFF_LOWER_UP) as u32;
+    let mut buf = Vec::new();
+    buf.extend(link(libc::RTM_NEWLINK, 2, running));
+    buf.extend(link(libc::RTM_NEWLINK, 2, libc::IFF_UP as u32));
+    buf.extend(link(libc::RTM_NEWLINK, 3, 0));
+    buf.extend(link(libc::RTM_NEWLINK, 2, libc::IFF_UP as u32));
+    buf.extend(link(libc::RTM_NEWLINK, 2, running));
+    buf.extend(addr(libc::RTM_NEWADDR, libc::AF_INET as u8, 2));
+    buf.extend(addr(libc::RTM_NEWADDR, libc::AF_INET6 as u8, 2));
+    buf.extend(addr(libc::RTM_DELADDR, libc::AF_INET as u8, 3));
+    buf.extend(link(libc::RTM_DELLINK, 2, running));
+
+    let mut up = true;
+    let mut index = 2;
+    assert_eq!(
+      parse_messages(&buf, &mut index, &mut up, || 2),
+      vec![
+        LinkEvent::Down,
+        LinkEvent::Up,
+        LinkEvent::AddressChanged,
+        LinkEvent::Down,
+      ]
+    );
+    assert!(!up);
+
+    // a truncated message is ignored
+    let mut up = true;
+    assert!(parse_messages(&buf[..20], &mut index, &mut up, || 2).is_empty());
+
+    // the interface is created again with another index
+    let mut buf = Vec::new();
+    buf.extend(link(libc::RTM_NEWLINK, 3, running));
+    buf.extend(link(libc::RTM_NEWLINK, 5, running));
+    buf.extend(addr(libc::RTM_NEWADDR, libc::AF_INET as u8, 5));
+    let mut up = false;
+    let mut index = 2;
+    assert_eq!(
+      parse_messages(&buf, &mut index, &mut up, || 5),
+      vec![LinkEvent::Up, LinkEvent::AddressChanged]
+    );
+    assert_eq!((index, up), (5, true));
+  }
+
+  #[test]
+  fn test_watch_loopback() {
+    let mut watcher = LinkWatcher::new("lo").unwrap();
+    assert!(watcher.is_up());
+    assert_eq!(
+      watcher.next_event(Some(Duration::from_millis(10))).unwrap(),
+      None
+    );
+    assert!(LinkWatcher::new("cygnus-missing0").is_err());
+
+    // a command ends the wait for a link that is down
+    watcher.up = false;
+    let mut checks = 0;
+    let error = watcher
+      .wait_up(|| {
+        checks += 1;
+        match checks {
+          1 => Ok(()),
+          _ => Err(AuthError::LogoutRequested),
+        }
+      })
+      .unwrap_err();
+    assert!(matches!(error, AuthError::LogoutRequested));
+    assert_eq!(checks, 2);
+  }
+}
@@ -0,0 +1,78 @@
+//! Sorts the datagrams received from the server into replies and the
+//! messages the server pushes on its own.
+
+/// Pushed by the server, with a subtype in the second byte.
+const MESSAGE: u8 = 0x4d;
+/// A notice to show to the user, such as maintenance announcements.
+const NOTICE: u8 = 0x38;
+/// The account was taken offline, by an administrator or a login elsewhere.
+const FORCED_OFFLINE: u8 = 0x15;
+/// The text of a message starts after the type, subtype and two bytes of
+/// length or padding.
+const TEXT_OFFSET: usize = 4;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ServerMessage<'a> {
+  /// An answer to a request, to be checked by the request itself.
+  Reply(&'a [u8]),
+  Notice(String),
+  ForcedOffline(String),
+  /// A message of a subtype nobody knows the meaning of.
+  Unknown(u8),
+}
+
+impl<'a> ServerMessage<'a> {
+  pub fn parse(buf: &'a [u8]) -> Self {
+    match buf {
+      [MESSAGE, NOTICE, ..] => Self::Notice(decode_text(buf)),
+      [MESSAGE, FORCED_OFFLINE, ..] => Self::ForcedOffline(decode_text(buf)),
+      [MESSAGE, subtype, ..] => Self::Unknown(*subtype),
+      _ => Self::Reply(buf),
+    }
+  }
+}
+
+/// Decodes the GBK text of a message, up to the first NUL.
+fn decode_text(buf: &[u8]) -> String {
+  let text = buf.get(TEXT_OFFSET..).unwrap_or_default();
+  let end = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+  let (text, _, _) = encoding_rs::GBK.decode(&text[..end]);
+  text.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_message() {
+    // "校园网维护" in GBK
+    let mut notice = vec![0x4d, 0x38, 0x00, 0x00];
+    notice.extend_from_slice(&[
+      0xd0, 0xa3, 0xd4, 0xb0, 0xcd, 0xf8, 0xce, 0xac, 0xbb, 0xa4,
+    ]);
+    notice.extend_from_slice(&[0, 0xff, 0xff]);
+    assert_eq!(
+      ServerMessage::parse(&notice),
+      ServerMessage::Notice("校园网维护".to_string())
+    );
+
+    let kicked = [0x4d, 0x15, 0x00, 0x00, b'b', b'y', b'e', b' '];
+    assert_eq!(
+      ServerMessage::parse(&kicked),
+      ServerMessage::ForcedOffline("bye".to_string())
+    );
+    assert_eq!(
+      ServerMessage::parse(&[0x4d, 0x15]),
+      ServerMessage::ForcedOffline(String::new())
+    );
+    assert_eq!(
+      ServerMessage::parse(&[0x4d, 0x3a]),
+      ServerMessage::Unknown(0x3a)
+    );
+    assert_eq!(
+      ServerMessage::parse(&[0x07, 0x00]),
+      ServerMessage::Reply(&[7, 0])
+    );
+  }
+}
@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+use super::budget::{Budget, BudgetUsage};
+use super::error::{AuthError, AuthResult};
+use super::schedule::{LocalTime, Schedule};
+
+/// Decides when [`DrClient::run`](super::DrClient::run) may be online, from
+/// the schedule and the online time budget.
+pub(crate) struct Policy {
+  schedule: Schedule,
+  budget: Option<BudgetUsage>,
+  online_since: Option<Instant>,
+}
+
+impl Policy {
+  pub fn new(schedule: &Schedule, budget: Option<&Budget>) -> Self {
+    Self {
+      schedule: schedule.clone(),
+      budget: budget.map(Budget::load),
+      online_since: None,
+    }
+  }
+
+  /// Why the client may not be online at `now`, and for how long.
+  pub fn blocked(&mut self, now: &LocalTime) -> Option<(AuthError, Duration)> {
+    if !self.schedule.is_open(now) {
+      let wait = self.schedule.next_change(now).unwrap_or(Duration::MAX);
+      return Some((AuthError::OutsideSchedule, wait));
+    }
+    let budget = self.budget.as_mut()?;
+    match budget.remaining(now).is_zero() {
+      true => Some((AuthError::BudgetExhausted, budget.until_reset(now))),
+      false => None,
+    }
+  }
+
+  /// How long the client may stay online from `now`, `None` for ever.
+  pub fn online_left(&mut self, now: &LocalTime) -> Option<Duration> {
+    let schedule = self.schedule.next_change(now);
+    let budget = self.budget.as_mut().map(|budget| budget.remaining(now));
+    match (schedule, budget) {
+      (Some(a), Some(b)) => Some(a.min(b)),
+      (a, b) => a.or(b),
+    }
+  }
+
+  /// Starts counting online time from `since`, when the login succeeded.
+  pub fn online(&mut self, since: Instant) {
+    self.online_since = Some(since);
+  }
+
+  /// Adds the time since the last call (or [`online`](Self::online)) to the
+  /// budget, and stops counting if `still_online` is false. Failing to save
+  /// the budget is not worth going offline for, so it is only logged.
+  pub fn record(&mut self, now: &LocalTime, still_online: bool) {
+    let since = match still_online {
+      true => self.online_since.replace(Instant::now()),
+      false => self.online_since.take(),
+    };
+    if let (Some(since), Some(budget)) = (since, self.budget.as_mut()) {
+      if let Err(e) = budget.record(since.elapsed(), now) {
+        warn!("Failed to save the online time budget: {}", e);
+      }
+    }
+  }
+
+  /// Fails when the client has to go offline at `now`.
+  pub fn check(&mut self, now: &LocalTime) -> AuthResult<()> {
+    match self.blocked(now) {
+      Some((error, _)) => Err(error),
+      None => Ok(()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::auth::budget::BudgetPeriod;
+  use crate::testing::temp_path;
+
+  #[test]
+  fn test_policy() {
+    let monday = |hour: u32| LocalTime {
+      year: 2024,
+      month: 9,
+      day: 2,
+      weekday: 0,
+      minute: hour * 60,
+      second: 0,
+      unix: None,
+    };
+    let path = temp_path("policy.json");
+    let schedule = Schedule::new(vec!["mon 08:00-12:00".parse().unwrap()]);
+    let budget =
+      Budget::new(Duration::from_secs(3600), BudgetPeriod::Daily).path(&path);
+    let mut policy = Policy::new(&schedule, Some(&budget));
+
+    let (error, wait) = policy.blocked(&monday(7)).unwrap();
+    assert!(matches!(error, AuthError::OutsideSchedule));
+    assert_eq!(wait, Duration::from_secs(3600));
+    assert!(policy.check(&monday(9)).is_ok());
+    // the budget runs out before the window closes
+    assert_eq!(
+      policy.online_left(&monday(9)),
+      Some(Duration::from_secs(3600))
+    );
+    assert_eq!(
+      policy.online_left(&monday(11)),
+      Some(Duration::from_secs(3600))
+    );
+
+    policy
+      .budget
+      .as_mut()
+      .unwrap()
+      .record(Duration::from_secs(3600), &monday(10))
+      .unwrap();
+    let (error, wait) = policy.blocked(&monday(10)).unwrap();
+    assert!(matches!(error, AuthError::BudgetExhausted));
+    assert_eq!(wait, Duration::from_secs(14 * 3600));
+
+    std::fs::remove_file(&path).unwrap();
+  }
+}
@@ -0,0 +1,262 @@
+//! Decodes the replies of the server. Every parser takes the datagram as
+//! received and returns `None` when it is too short or of another type,
+//! never reading past its end.
+
+use std::net::Ipv4Addr;
+
+use super::account::AccountInfo;
+
+/// Reads `N` bytes at `offset`, if the reply is long enough.
+fn field<const N: usize>(reply: &[u8], offset: usize) -> Option<[u8; N]> {
+  reply.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+/// The answer to a challenge (0x02).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChallengeReply {
+  pub salt: [u8; 4],
+  /// The address the server sees the client at.
+  pub client_ip: Ipv4Addr,
+}
+
+impl ChallengeReply {
+  pub fn parse(reply: &[u8]) -> Option<Self> {
+    match reply {
+      [0x02, ..] => Some(Self {
+        salt: field(reply, 4)?,
+        client_ip: Ipv4Addr::from(field::<4>(reply, 20)?),
+      }),
+      _ => None,
+    }
+  }
+}
+
+/// Codes of a login failure, in the fifth byte.
+const ACCOUNT_IN_USE: u8 = 0x01;
+const INSUFFICIENT_BALANCE: u8 = 0x04;
+const ACCOUNT_SUSPENDED: u8 = 0x05;
+const INVALID_MAC: u8 = 0x0b;
+
+/// Offsets of the account usage in a login success reply, little endian
+/// `u32`s. They are this client's own reading of the reply and have not been
+/// checked against a reference implementation or a capture.
+const USED_MINUTES: usize = 5;
+const USED_TRAFFIC: usize = 9;
+const BALANCE: usize = 13;
+
+/// Reads the account usage from a login success reply.
+fn account(reply: &[u8]) -> Option<AccountInfo> {
+  let read = |offset| field(reply, offset).map(u32::from_le_bytes);
+  Some(AccountInfo {
+    used_minutes: read(USED_MINUTES)?,
+    used_traffic_kib: read(USED_TRAFFIC)?,
+    balance_cents: read(BALANCE)?,
+  })
+}
+
+/// The answer to a login (0x04 on success, 0x05 on failure) or a logout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginReply {
+  Success {
+    /// Authenticates the keep alive and logout packets of the session.
+    tail: [u8; 16],
+    account: Option<AccountInfo>,
+  },
+  /// The account is already online elsewhere.
+  AccountInUse,
+  /// The balance or the included time or traffic is used up.
+  InsufficientBalance,
+  AccountSuspended,
+  InvalidMac,
+  /// A wrong password, or a failure the code of which is unknown.
+  InvalidCredentials,
+}
+
+impl LoginReply {
+  pub fn parse(reply: &[u8]) -> Option<Self> {
+    match reply {
+      [0x04, ..] => Some(Self::Success {
+        tail: field(reply, 23)?,
+        account: account(reply),
+      }),
+      [0x05, _, _, _, ACCOUNT_IN_USE, ..] => Some(Self::AccountInUse),
+      [0x05, _, _, _, INSUFFICIENT_BALANCE, ..] => {
+        Some(Self::InsufficientBalance)
+      }
+      [0x05, _, _, _, ACCOUNT_SUSPENDED, ..] => Some(Self::AccountSuspended),
+      [0x05, _, _, _, INVALID_MAC, ..] => Some(Self::InvalidMac),
+      [0x05, ..] => Some(Self::InvalidCredentials),
+      _ => None,
+    }
+  }
+}
+
+/// The third byte of a reply to a 40 byte keep alive packet, its length.
+const KEEP_ALIVE_40_LEN: u8 = 0x28;
+/// The third byte of the shorter "file" reply the server may send to the
+/// extra 40 byte packet instead.
+const FILE_REPLY_LEN: u8 = 0x10;
+
+/// The answer to a keep alive packet (0x07). Which fields matter depends on
+/// the packet it answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepAliveReply<'a>(&'a [u8]);
+
+impl<'a> KeepAliveReply<'a> {
+  pub fn parse(reply: &'a [u8]) -> Option<Self> {
+    match reply {
+      [0x07, ..] => Some(Self(reply)),
+      _ => None,
+    }
+  }
+
+  /// Whether `reply` answers the 38 byte packet: long enough to hold the
+  /// version, and not a late reply to a 40 byte packet.
+  pub fn answers_38(reply: &[u8]) -> bool {
+    reply.len() >= 30
+      && matches!(
+        reply,
+        [0x07, _, len, ..] if *len != KEEP_ALIVE_40_LEN && *len != FILE_REPLY_LEN
+      )
+  }
+
+  /// Whether `reply` answers the 40 byte `request`, echoing its counter and
+  /// moving to the next step, and not an earlier request.
+  ///
+  /// The extra packet and the first packet after it share the counter and
+  /// the step, so a late full reply to the extra packet still passes.
+  pub fn answers_40(reply: &[u8], request: &[u8; 40]) -> bool {
+    let extra = request[6..8] == [0x0f, 0x27];
+    match reply {
+      [0x07, count, FILE_REPLY_LEN, ..] => extra && *count == request[1],
+      [0x07, count, KEEP_ALIVE_40_LEN, _, 0x0b, step, ..] => {
+        reply.len() >= 40
+          && *count == request[1]
+          && *step == request[5].wrapping_add(1)
+      }
+      _ => false,
+    }
+  }
+
+  /// The version to send in the following 40 byte packets, from the answer
+  /// to the 38 byte packet.
+  pub fn version(&self) -> Option<(u8, u8)> {
+    field::<2>(self.0, 28).map(|[major, minor]| (major, minor))
+  }
+
+  /// The tail to send in the next 40 byte packet.
+  pub fn tail(&self) -> Option<[u8; 4]> {
+    field(self.0, 16)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use proptest::prelude::*;
+
+  use super::*;
+
+  #[test]
+  fn test_parse_replies() {
+    let mut challenge = [0u8; 24];
+    challenge[0] = 0x02;
+    challenge[4..8].copy_from_slice(&[1, 2, 3, 4]);
+    challenge[20..24].copy_from_slice(&[10, 0, 0, 7]);
+    assert_eq!(
+      ChallengeReply::parse(&challenge),
+      Some(ChallengeReply {
+        salt: [1, 2, 3, 4],
+        client_ip: Ipv4Addr::new(10, 0, 0, 7),
+      })
+    );
+    assert_eq!(ChallengeReply::parse(&challenge[..23]), None);
+    assert_eq!(ChallengeReply::parse(&[0x04; 24]), None);
+
+    let mut login = [0u8; 39];
+    login[0] = 0x04;
+    login[23..39].copy_from_slice(&[0xab; 16]);
+    match LoginReply::parse(&login) {
+      Some(LoginReply::Success { tail, account }) => {
+        assert_eq!(tail, [0xab; 16]);
+        assert_eq!(account.unwrap().used_minutes, 0);
+      }
+      other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(LoginReply::parse(&login[..38]), None);
+    login[5..9].copy_from_slice(&1500u32.to_le_bytes());
+    login[9..13].copy_from_slice(&(300 * 1024u32).to_le_bytes());
+    login[13..17].copy_from_slice(&850u32.to_le_bytes());
+    assert_eq!(
+      account(&login),
+      Some(AccountInfo {
+        used_minutes: 1500,
+        used_traffic_kib: 300 * 1024,
+        balance_cents: 850,
+      })
+    );
+    assert_eq!(account(&login[..16]), None);
+    assert_eq!(
+      LoginReply::parse(&[0x05, 0, 0, 0, 0x0b]),
+      Some(LoginReply::InvalidMac)
+    );
+    assert_eq!(
+      LoginReply::parse(&[0x05, 0, 0, 0, 0x01]),
+      Some(LoginReply::AccountInUse)
+    );
+    assert_eq!(
+      LoginReply::parse(&[0x05, 0, 0, 0, 0x04]),
+      Some(LoginReply::InsufficientBalance)
+    );
+    assert_eq!(
+      LoginReply::parse(&[0x05]),
+      Some(LoginReply::InvalidCredentials)
+    );
+    assert_eq!(LoginReply::parse(&[]), None);
+
+    let mut keep_alive = [0u8; 30];
+    keep_alive[0] = 0x07;
+    keep_alive[16..20].copy_from_slice(&[9; 4]);
+    keep_alive[28..30].copy_from_slice(&[0xdc, 0x02]);
+    let reply = KeepAliveReply::parse(&keep_alive).unwrap();
+    assert_eq!(reply.version(), Some((0xdc, 0x02)));
+    assert_eq!(reply.tail(), Some([9; 4]));
+    let short = KeepAliveReply::parse(&keep_alive[..20]).unwrap();
+    assert_eq!((short.version(), short.tail()), (None, Some([9; 4])));
+    assert_eq!(KeepAliveReply::parse(&[0x02]), None);
+
+    assert!(KeepAliveReply::answers_38(&keep_alive));
+    assert!(!KeepAliveReply::answers_38(&keep_alive[..29]));
+    let mut request = [0u8; 40];
+    request[..6].copy_from_slice(&[0x07, 5, 0x28, 0x00, 0x0b, 0x01]);
+    let mut reply_40 = [0u8; 40];
+    reply_40[..6].copy_from_slice(&[0x07, 5, 0x28, 0x00, 0x0b, 0x02]);
+    assert!(KeepAliveReply::answers_40(&reply_40, &request));
+    assert!(!KeepAliveReply::answers_38(&reply_40));
+    assert!(!KeepAliveReply::answers_40(&reply_40[..39], &request));
+    assert!(!KeepAliveReply::answers_40(&keep_alive, &request));
+    // the reply to the previous request has another counter or step
+    request[1] = 6;
+    assert!(!KeepAliveReply::answers_40(&reply_40, &request));
+    request[1] = 5;
+    request[5] = 0x03;
+    assert!(!KeepAliveReply::answers_40(&reply_40, &request));
+    // only the extra packet may be answered by a file reply
+    let file = [0x07, 5, 0x10, 0x00];
+    request[5] = 0x01;
+    assert!(!KeepAliveReply::answers_40(&file, &request));
+    request[6..8].copy_from_slice(&[0x0f, 0x27]);
+    assert!(KeepAliveReply::answers_40(&file, &request));
+  }
+
+  proptest! {
+    #[test]
+    fn test_parse_any_bytes(reply in proptest::collection::vec(any::<u8>(), 0..64)) {
+      let _ = ChallengeReply::parse(&reply);
+      let _ = LoginReply::parse(&reply);
+      if let Some(keep_alive) = KeepAliveReply::parse(&reply) {
+        prop_assert_eq!(keep_alive.version().is_some(), reply.len() >= 30);
+        prop_assert_eq!(keep_alive.tail().is_some(), reply.len() >= 20);
+      }
+    }
+  }
+}
@@ -0,0 +1,380 @@
+//! Weekly windows in which the client is allowed to be online.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::time::{civil_from_days, utc_offset};
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// A point in local time, as far as schedules and budgets care.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+  pub year: i32,
+  /// 1 to 12.
+  pub month: u32,
+  /// 1 to 31.
+  pub day: u32,
+  /// 0 is Monday.
+  pub weekday: u32,
+  /// Minute of the day, 0 to 1439.
+  pub minute: u32,
+  pub second: u32,
+  /// Seconds since the Unix epoch when read from the clock, to follow
+  /// daylight saving time changes. Without it every day is 24 hours long.
+  pub(crate) unix: Option<i64>,
+}
+
+impl LocalTime {
+  pub fn now() -> Self {
+    let secs = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs() as i64)
+      .unwrap_or_default();
+    Self::from_unix(secs)
+  }
+
+  #[cfg(unix)]
+  fn from_unix(secs: i64) -> Self {
+    // SAFETY: tm is plain data and localtime_r only writes to it
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let time = secs as libc::time_t;
+    // SAFETY: both pointers are valid for the duration of the call
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+      return Self::from_utc(secs);
+    }
+    Self {
+      year: tm.tm_year + 1900,
+      month: tm.tm_mon as u32 + 1,
+      day: tm.tm_mday as u32,
+      weekday: (tm.tm_wday as u32 + 6) % 7,
+      minute: tm.tm_hour as u32 * 60 + tm.tm_min as u32,
+      second: tm.tm_sec as u32,
+      unix: Some(secs),
+    }
+  }
+
+  #[cfg(not(unix))]
+  fn from_unix(secs: i64) -> Self {
+    Self::from_utc(secs)
+  }
+
+  fn from_utc(secs: i64) -> Self {
+    let days = secs.div_euclid(86400);
+    let time = secs.rem_euclid(86400) as u32;
+    let (year, month, day) = civil_from_days(days);
+    Self {
+      year: year as i32,
+      month,
+      day,
+      // 1970-01-01 was a Thursday
+      weekday: (days + 3).rem_euclid(7) as u32,
+      minute: time / 60,
+      second: time % 60,
+      unix: None,
+    }
+  }
+
+  /// Time until the next local midnight.
+  pub fn until_midnight(&self) -> Duration {
+    self.until_midnight_after(1, utc_offset)
+  }
+
+  /// Time until the first day of the next month.
+  pub fn until_next_month(&self) -> Duration {
+    let days = days_in_month(self.year, self.month) - self.day + 1;
+    self.until_midnight_after(days, utc_offset)
+  }
+
+  /// Time until the midnight `days` days from today, one hour more or less
+  /// when the offset from UTC changes on the way.
+  fn until_midnight_after(
+    &self,
+    days: u32,
+    utc_offset: impl Fn(i64) -> i64,
+  ) -> Duration {
+    let elapsed = i64::from(self.minute * 60 + self.second);
+    let local = i64::from(days) * i64::from(MINUTES_PER_DAY * 60) - elapsed;
+    let shift = self
+      .unix
+      .map_or(0, |now| utc_offset(now + local) - utc_offset(now));
+    Duration::from_secs((local - shift).max(0) as u64)
+  }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+  match month {
+    2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+    2 => 28,
+    4 | 6 | 9 | 11 => 30,
+    _ => 31,
+  }
+}
+
+/// A time range on some weekdays, such as `mon-fri 08:00-23:30`.
+///
+/// The days are a comma separated list of names or ranges (`sat,sun`,
+/// `fri-mon`), all days when omitted or `*`. A range ending before it starts
+/// runs past midnight into the next day, and equal start and end times cover
+/// the whole day. `24:00` may only end a range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+  /// Bit `n` is set for weekday `n`, 0 being Monday.
+  days: u8,
+  start: u32,
+  end: u32,
+}
+
+impl Window {
+  fn has_day(&self, weekday: u32) -> bool {
+    self.days & (1 << weekday) != 0
+  }
+
+  pub fn contains(&self, weekday: u32, minute: u32) -> bool {
+    let yesterday = (weekday + 6) % 7;
+    if self.start == self.end {
+      self.has_day(weekday)
+    } else if self.start < self.end {
+      self.has_day(weekday) && (self.start..self.end).contains(&minute)
+    } else {
+      (self.has_day(weekday) && minute >= self.start)
+        || (self.has_day(yesterday) && minute < self.end)
+    }
+  }
+}
+
+impl FromStr for Window {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let parts = s.split_whitespace().collect::<Vec<_>>();
+    let (days, times) = match parts.as_slice() {
+      [times] => (0x7f, *times),
+      [days, times] => (parse_days(days)?, *times),
+      _ => return Err(format!("invalid window `{}`", s)),
+    };
+    let (start, end) = times
+      .split_once('-')
+      .ok_or_else(|| format!("invalid time range `{}`", times))?;
+    let start = parse_time(start)?;
+    if start == MINUTES_PER_DAY {
+      return Err(format!("a window cannot start at 24:00, in `{}`", s));
+    }
+    Ok(Self {
+      days,
+      start,
+      end: parse_time(end)? % MINUTES_PER_DAY,
+    })
+  }
+}
+
+impl fmt::Display for Window {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let days = (0..7)
+      .filter(|&day| self.has_day(day))
+      .map(|day| DAY_NAMES[day as usize])
+      .collect::<Vec<_>>()
+      .join(",");
+    write!(
+      f,
+      "{} {:02}:{:02}-{:02}:{:02}",
+      days,
+      self.start / 60,
+      self.start % 60,
+      self.end / 60,
+      self.end % 60
+    )
+  }
+}
+
+fn parse_day(s: &str) -> Result<u32, String> {
+  let lower = s.to_ascii_lowercase();
+  DAY_NAMES
+    .iter()
+    .position(|name| lower.len() >= 3 && lower.starts_with(name))
+    .map(|day| day as u32)
+    .ok_or_else(|| format!("invalid weekday `{}`", s))
+}
+
+fn parse_days(s: &str) -> Result<u8, String> {
+  if s == "*" {
+    return Ok(0x7f);
+  }
+  let mut days = 0u8;
+  for part in s.split(',') {
+    let (first, last) = match part.split_once('-') {
+      Some((first, last)) => (parse_day(first)?, parse_day(last)?),
+      None => (parse_day(part)?, parse_day(part)?),
+    };
+    let mut day = first;
+    loop {
+      days |= 1 << day;
+      if day == last {
+        break;
+      }
+      day = (day + 1) % 7;
+    }
+  }
+  Ok(days)
+}
+
+fn parse_time(s: &str) -> Result<u32, String> {
+  let invalid = || format!("invalid time `{}`, expected HH:MM", s);
+  let (hour, minute) = s.split_once(':').ok_or_else(invalid)?;
+  let hour = hour.parse::<u32>().map_err(|_| invalid())?;
+  let minute = minute.parse::<u32>().map_err(|_| invalid())?;
+  if minute >= 60 || hour > 24 || (hour == 24 && minute != 0) {
+    return Err(invalid());
+  }
+  Ok(hour * 60 + minute)
+}
+
+/// The union of several windows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schedule {
+  windows: Vec<Window>,
+}
+
+impl Schedule {
+  pub fn new(windows: Vec<Window>) -> Self {
+    Self { windows }
+  }
+
+  pub fn windows(&self) -> &[Window] {
+    &self.windows
+  }
+
+  /// Whether `now` is in a window. An empty schedule is always open.
+  pub fn is_open(&self, now: &LocalTime) -> bool {
+    self.is_open_at(now.weekday, now.minute)
+  }
+
+  fn is_open_at(&self, weekday: u32, minute: u32) -> bool {
+    self.windows.is_empty()
+      || self.windows.iter().any(|w| w.contains(weekday, minute))
+  }
+
+  /// Time until the schedule opens or closes, `None` if it never does.
+  pub fn next_change(&self, now: &LocalTime) -> Option<Duration> {
+    let open = self.is_open(now);
+    // a week and a day covers windows running past midnight
+    (1..=8 * MINUTES_PER_DAY)
+      .find(|offset| {
+        let minute = now.minute + offset;
+        let weekday = (now.weekday + minute / MINUTES_PER_DAY) % 7;
+        self.is_open_at(weekday, minute % MINUTES_PER_DAY) != open
+      })
+      .map(|offset| {
+        Duration::from_secs(u64::from(offset * 60 - now.second.min(59)))
+      })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn at(weekday: u32, hour: u32, minute: u32) -> LocalTime {
+    LocalTime {
+      year: 2024,
+      month: 9,
+      day: 2 + weekday,
+      weekday,
+      minute: hour * 60 + minute,
+      second: 0,
+      unix: None,
+    }
+  }
+
+  #[test]
+  fn test_parse_window() {
+    let window = "mon-fri 08:00-23:30".parse::<Window>().unwrap();
+    assert_eq!(window.to_string(), "mon,tue,wed,thu,fri 08:00-23:30");
+    let window = "Sat,sunday 22:00-02:00".parse::<Window>().unwrap();
+    assert_eq!(window.to_string(), "sat,sun 22:00-02:00");
+    let window = "fri-mon 00:00-24:00".parse::<Window>().unwrap();
+    assert_eq!(window.to_string(), "mon,fri,sat,sun 00:00-00:00");
+    let window = "07:00-12:00".parse::<Window>().unwrap();
+    assert_eq!(window, "* 07:00-12:00".parse().unwrap());
+
+    for invalid in [
+      "",
+      "mon",
+      "mon 8-9",
+      "xyz 08:00-09:00",
+      "08:00-25:00",
+      "24:00-02:00",
+    ] {
+      assert!(invalid.parse::<Window>().is_err(), "{}", invalid);
+    }
+  }
+
+  #[test]
+  fn test_window_contains() {
+    let window = "mon-fri 08:00-23:30".parse::<Window>().unwrap();
+    assert!(window.contains(0, 8 * 60));
+    assert!(!window.contains(0, 23 * 60 + 30));
+    assert!(!window.contains(5, 12 * 60));
+
+    // runs past midnight into monday
+    let window = "sun 22:00-02:00".parse::<Window>().unwrap();
+    assert!(window.contains(6, 23 * 60));
+    assert!(window.contains(0, 60));
+    assert!(!window.contains(6, 60));
+    assert!(!window.contains(0, 23 * 60));
+  }
+
+  #[test]
+  fn test_schedule_next_change() {
+    let schedule = Schedule::new(vec![
+      "mon-fri 08:00-12:00".parse().unwrap(),
+      "mon-fri 13:00-23:00".parse().unwrap(),
+    ]);
+    assert!(schedule.is_open(&at(0, 9, 0)));
+    assert_eq!(
+      schedule.next_change(&at(0, 9, 0)),
+      Some(Duration::from_secs(3 * 3600))
+    );
+    assert!(!schedule.is_open(&at(4, 23, 30)));
+    // friday night until monday morning
+    assert_eq!(
+      schedule.next_change(&at(4, 23, 30)),
+      Some(Duration::from_secs((2 * 24 + 8) * 3600 + 30 * 60))
+    );
+
+    let always = Schedule::default();
+    assert!(always.is_open(&at(3, 3, 0)));
+    assert_eq!(always.next_change(&at(3, 3, 0)), None);
+  }
+
+  #[test]
+  fn test_local_time() {
+    // 2024-09-01 23:59:30 UTC, a Sunday
+    let now = LocalTime::from_utc(19967 * 86400 + 86370);
+    assert_eq!((now.year, now.month, now.day), (2024, 9, 1));
+    assert_eq!((now.weekday, now.minute, now.second), (6, 1439, 30));
+    assert_eq!(now.until_midnight(), Duration::from_secs(30));
+    assert_eq!(now.until_next_month(), Duration::from_secs(30 + 29 * 86400));
+  }
+
+  #[test]
+  fn test_until_midnight_across_dst() {
+    // 2024-03-30 12:00 in a zone that moves from UTC+1 to UTC+2 at
+    // 2024-03-31 01:00 UTC
+    let change = 19813 * 86400 + 3600;
+    let offset = |secs: i64| if secs < change { 3600 } else { 7200 };
+    let mut now = LocalTime::from_utc(19812 * 86400 + 12 * 3600);
+    now.unix = Some(19812 * 86400 + 11 * 3600);
+    assert_eq!(
+      now.until_midnight_after(1, offset),
+      Duration::from_secs(43200)
+    );
+    // the next day only has 23 hours
+    assert_eq!(
+      now.until_midnight_after(2, offset),
+      Duration::from_secs(43200 + 23 * 3600)
+    );
+  }
+}
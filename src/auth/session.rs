@@ -0,0 +1,494 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, error, info, warn};
+
+use crate::user::{secret::SecretBuf, User};
+
+use super::{
+  account::AccountInfo,
+  context::DrContext,
+  data::AliveType,
+  env::{DrEnv, SystemEnv},
+  error::{AuthError, AuthResult},
+  message::ServerMessage,
+  reply::{ChallengeReply, KeepAliveReply, LoginReply},
+};
+
+/// Progress reported while a session is running.
+#[derive(Debug)]
+pub enum Event {
+  /// The server answered the challenge and assigned this address.
+  Challenged { client_ip: Ipv4Addr },
+  /// The login was accepted, the client is online.
+  Online { client_ip: Ipv4Addr },
+  /// The login reply reported the usage of the account.
+  Account { info: AccountInfo },
+  /// A keep alive round was accepted, `round` counts from 1.
+  KeepAlive { round: u64 },
+  /// The session ended with an error.
+  Offline { error: AuthError },
+  /// A new session will be started after `delay`.
+  Retrying { delay: Duration },
+  /// The server pushed a notice.
+  Notice { message: String },
+  /// Staying offline for `duration`, outside the schedule or the budget.
+  Idle { duration: Duration },
+  /// Sessions log in with the account at `index` (from 0) of the list,
+  /// read from `source`.
+  UsingAccount { index: usize, source: String },
+}
+
+/// A single authenticated session with the server.
+///
+/// The steps can be driven one by one with [`challenge`](Self::challenge),
+/// [`login`](Self::login) and [`keep_alive`](Self::keep_alive), or all at
+/// once with [`run`](Self::run).
+pub struct Session<E: DrEnv = SystemEnv> {
+  ctx: DrContext<E>,
+  challenge_tries: u8,
+  keep_alive_interval: Duration,
+  keep_40_count: u8,
+  rounds: u64,
+  notices: VecDeque<String>,
+  account: Option<AccountInfo>,
+}
+
+impl<E: DrEnv> Session<E> {
+  pub fn new(
+    ctx: DrContext<E>,
+    challenge_tries: u8,
+    keep_alive_interval: Duration,
+  ) -> Self {
+    Self {
+      ctx,
+      challenge_tries,
+      keep_alive_interval,
+      keep_40_count: 0,
+      rounds: 0,
+      notices: VecDeque::new(),
+      account: None,
+    }
+  }
+
+  pub fn user(&self) -> &User {
+    &self.ctx.user
+  }
+
+  /// The address of the server in use.
+  pub fn server(&self) -> SocketAddr {
+    self.ctx.server
+  }
+
+  /// The address assigned by the server, unspecified before the challenge.
+  pub fn client_ip(&self) -> Ipv4Addr {
+    Ipv4Addr::from(self.ctx.data.client_ip)
+  }
+
+  /// Number of accepted keep alive rounds.
+  pub fn rounds(&self) -> u64 {
+    self.rounds
+  }
+
+  pub fn context(&self) -> &DrContext<E> {
+    &self.ctx
+  }
+
+  /// The usage of the account, known after login.
+  pub fn account(&self) -> Option<AccountInfo> {
+    self.account
+  }
+
+  /// Takes the notices pushed by the server since the last call.
+  pub fn take_notices(&mut self) -> Vec<String> {
+    self.notices.drain(..).collect()
+  }
+
+  /// Runs the challenge, login and keep alive steps until one of them fails.
+  pub fn run(
+    &mut self,
+    on_event: impl FnMut(&Event),
+  ) -> AuthResult<std::convert::Infallible> {
+    self.run_with(on_event, |env, interval| {
+      env.sleep(interval);
+      Ok(())
+    })
+  }
+
+  /// Like [`run`](Self::run), but calls `wait` between keep alive rounds
+  /// instead of sleeping. An error from `wait` ends the session.
+  #[tracing::instrument(
+    skip_all,
+    name = "run",
+    fields(user = %self.ctx.user.username_hash(), server = %self.ctx.server)
+  )]
+  pub fn run_with(
+    &mut self,
+    mut on_event: impl FnMut(&Event),
+    mut wait: impl FnMut(&E, Duration) -> AuthResult<()>,
+  ) -> AuthResult<std::convert::Infallible> {
+    let client_ip = self.challenge()?;
+    on_event(&Event::Challenged { client_ip });
+    self.login()?;
+    self.emit_notices(&mut on_event);
+    on_event(&Event::Online { client_ip });
+    if let Some(info) = self.account {
+      on_event(&Event::Account { info });
+    }
+    loop {
+      let result = self.keep_alive();
+      self.emit_notices(&mut on_event);
+      result?;
+      on_event(&Event::KeepAlive { round: self.rounds });
+      wait(&self.ctx.env, self.keep_alive_interval)?;
+    }
+  }
+
+  fn emit_notices(&mut self, on_event: &mut impl FnMut(&Event)) {
+    for message in self.notices.drain(..) {
+      on_event(&Event::Notice { message });
+    }
+  }
+
+  /// Sends `request` and receives into `reply` the first datagram that
+  /// `is_reply` accepts, returning its length. Notices are queued, a forced
+  /// offline message ends the session and anything else (such as a late
+  /// reply to an earlier request) is dropped.
+  fn exchange(
+    &mut self,
+    request: &[u8],
+    reply: &mut [u8],
+    is_reply: impl Fn(&[u8]) -> bool,
+  ) -> AuthResult<usize> {
+    let client = &self.ctx.client;
+    client.send(request)?;
+    let timeout = client.read_timeout()?;
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let result = self.receive(reply, is_reply, deadline);
+    self.ctx.client.set_read_timeout(timeout)?;
+    result
+  }
+
+  fn receive(
+    &mut self,
+    reply: &mut [u8],
+    is_reply: impl Fn(&[u8]) -> bool,
+    deadline: Option<Instant>,
+  ) -> AuthResult<usize> {
+    loop {
+      if let Some(deadline) = deadline {
+        let left = deadline
+          .checked_duration_since(Instant::now())
+          .filter(|left| !left.is_zero())
+          .ok_or(io::Error::from(io::ErrorKind::TimedOut))?;
+        self.ctx.client.set_read_timeout(Some(left))?;
+      }
+      reply.fill(0);
+      let len = self.ctx.client.recv(reply)?;
+      match ServerMessage::parse(&reply[..len]) {
+        ServerMessage::Reply(packet) if is_reply(packet) => return Ok(len),
+        ServerMessage::Reply(packet) => {
+          debug!(kind = packet.first(), len, "Dropping unexpected packet");
+        }
+        ServerMessage::Notice(message) => {
+          info!(%message, "Server notice");
+          self.notices.push_back(message);
+        }
+        ServerMessage::ForcedOffline(message) => {
+          warn!(%message, "Forced offline by the server");
+          return Err(AuthError::ForcedOffline(message));
+        }
+        ServerMessage::Unknown(subtype) => {
+          debug!(subtype, "Ignoring server message");
+        }
+      }
+    }
+  }
+
+  #[tracing::instrument(skip_all)]
+  pub fn challenge(&mut self) -> AuthResult<Ipv4Addr> {
+    info!("Starting challenge");
+    for try_times in 0..self.challenge_tries {
+      info!("Challenge try: {}", try_times + 1);
+
+      let mut send_buf = [0; 20];
+      let mut recv_buf = [0; 200];
+
+      self.ctx.get_challenge_data(try_times, &mut send_buf);
+
+      let is_reply = |r: &[u8]| matches!(r, [0x02, ..]);
+      let reply =
+        self
+          .exchange(&send_buf, &mut recv_buf, is_reply)
+          .and_then(|len| {
+            ChallengeReply::parse(&recv_buf[..len])
+              .ok_or(AuthError::MalformedReply("challenge"))
+          });
+      let reply = match reply {
+        Ok(reply) => reply,
+        Err(e @ AuthError::ForcedOffline(_)) => return Err(e),
+        Err(e) => {
+          warn!("Challenge failed, retrying: {}", e);
+          continue;
+        }
+      };
+      self.ctx.data.salt = reply.salt;
+      self.ctx.data.client_ip = reply.client_ip.octets();
+      let client_ip = reply.client_ip;
+      info!(state = "challenged", %client_ip, "Challenge succeeded");
+      return Ok(client_ip);
+    }
+
+    error!("Challenge max tries exceeded");
+    Err(AuthError::ChallengeMaxTriesExceeded)
+  }
+
+  #[tracing::instrument(skip_all)]
+  pub fn login(&mut self) -> AuthResult<()> {
+    info!("Starting login");
+    let mut send_buf = SecretBuf::new(400);
+    let mut recv_buf = [0; 200];
+
+    self.ctx.get_login_data(&mut send_buf)?;
+    let len = self
+      .exchange(&send_buf, &mut recv_buf, |r| matches!(r, [0x04 | 0x05, ..]))?;
+
+    match LoginReply::parse(&recv_buf[..len]) {
+      Some(LoginReply::Success { tail, account }) => {
+        info!(
+          state = "online",
+          client_ip = %Ipv4Addr::from(self.ctx.data.client_ip),
+          "Login success"
+        );
+        self.ctx.data.tail = tail;
+        self.account = account;
+        if let Some(info) = &self.account {
+          info!(
+            used_minutes = info.used_minutes,
+            used_traffic_kib = info.used_traffic_kib,
+            balance_cents = info.balance_cents,
+            "Account {}",
+            info
+          );
+        }
+        Ok(())
+      }
+      Some(LoginReply::AccountInUse) => {
+        error!("Login failed: account in use");
+        Err(AuthError::AccountInUse)
+      }
+      Some(LoginReply::InsufficientBalance) => {
+        error!("Login failed: insufficient balance");
+        Err(AuthError::InsufficientBalance)
+      }
+      Some(LoginReply::AccountSuspended) => {
+        error!("Login failed: account suspended");
+        Err(AuthError::AccountSuspended)
+      }
+      Some(LoginReply::InvalidMac) => {
+        error!("Login failed: invalid mac");
+        Err(AuthError::InvalidMacAddress)
+      }
+      Some(LoginReply::InvalidCredentials) => {
+        error!("Login failed: invalid username or password");
+        Err(AuthError::InvalidUsernameOrPassword)
+      }
+      None => {
+        error!("Login failed: malformed reply");
+        Err(AuthError::MalformedReply("login"))
+      }
+    }
+  }
+
+  /// Tells the server the client goes offline. The session cannot be used
+  /// afterwards.
+  #[tracing::instrument(skip_all)]
+  pub fn logout(&mut self) -> AuthResult<()> {
+    info!("Starting logout");
+    self.challenge()?;
+    let mut send_buf = [0; 80];
+    let mut recv_buf = [0; 200];
+
+    self.ctx.get_logout_data(&mut send_buf);
+    let len = self
+      .exchange(&send_buf, &mut recv_buf, |r| matches!(r, [0x04 | 0x05, ..]))?;
+
+    if let [0x04, ..] = recv_buf[..len] {
+      info!(state = "offline", "Logout success");
+      return Ok(());
+    }
+
+    error!("Logout failed");
+    Err(AuthError::LogoutRejected)
+  }
+
+  /// Runs one keep alive round. The server expects a round about every 20
+  /// seconds.
+  #[tracing::instrument(skip_all)]
+  pub fn keep_alive(&mut self) -> AuthResult<()> {
+    info!("Sending keep alive data");
+    let mut send_buf_38 = [0; 38];
+    let mut send_buf_40 = [0; 40];
+    let mut recv_buf = [0; 300];
+
+    self.ctx.get_keep_alive_data_38(&mut send_buf_38);
+    let len =
+      self.exchange(&send_buf_38, &mut recv_buf, KeepAliveReply::answers_38)?;
+    self.ctx.data.keep_alive_version = KeepAliveReply::parse(&recv_buf[..len])
+      .and_then(|reply| reply.version())
+      .ok_or(AuthError::MalformedReply("keep alive"))?;
+
+    if self.keep_40_count.is_multiple_of(21) {
+      self.ctx.get_keep_alive_data_40(
+        AliveType::EXTRA,
+        self.keep_40_count,
+        &mut send_buf_40,
+      );
+      self.exchange(&send_buf_40, &mut recv_buf, |r| {
+        KeepAliveReply::answers_40(r, &send_buf_40)
+      })?;
+      info!("Keep alive extra accepted");
+    }
+
+    self.ctx.get_keep_alive_data_40(
+      AliveType::FIRST,
+      self.keep_40_count,
+      &mut send_buf_40,
+    );
+    let len = self.exchange(&send_buf_40, &mut recv_buf, |r| {
+      KeepAliveReply::answers_40(r, &send_buf_40)
+    })?;
+    self.ctx.data.tail_2 = KeepAliveReply::parse(&recv_buf[..len])
+      .and_then(|reply| reply.tail())
+      .ok_or(AuthError::MalformedReply("keep alive"))?;
+    self.keep_40_count = self.keep_40_count.wrapping_add(1);
+    info!("Keep alive first accepted");
+
+    self.ctx.get_keep_alive_data_40(
+      AliveType::SECOND,
+      self.keep_40_count,
+      &mut send_buf_40,
+    );
+    self.exchange(&send_buf_40, &mut recv_buf, |r| {
+      KeepAliveReply::answers_40(r, &send_buf_40)
+    })?;
+    self.keep_40_count = self.keep_40_count.wrapping_add(1);
+    self.rounds += 1;
+    info!(state = "online", round = self.rounds, "Keep alive accepted");
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::auth::DrClient;
+  use crate::testing::{reply, MockServer};
+
+  /// Answers like the server. Before each keep alive reply it pushes a
+  /// notice and a stray packet, and it kicks the client on the third round.
+  fn mock_server(accept: bool) -> MockServer {
+    let mut rounds = 0;
+    MockServer::new(move |request| match request[0] {
+      0xff if rounds == 2 => vec![b"\x4d\x15\x00\x00kicked".to_vec()],
+      0xff => {
+        rounds += 1;
+        vec![
+          b"\x4d\x38\x00\x00notice\x00".to_vec(),
+          vec![0x04; 8],
+          reply(request, accept).unwrap(),
+        ]
+      }
+      _ => reply(request, accept).into_iter().collect(),
+    })
+  }
+
+  fn session(server: &str) -> Session {
+    let user =
+      User::new("user".into(), "password".into(), [0, 1, 2, 3, 4, 5]).unwrap();
+    DrClient::builder()
+      .server(server)
+      .timeout(Duration::from_secs(2))
+      .build()
+      .connect(user)
+      .unwrap()
+  }
+
+  #[test]
+  fn test_session() {
+    let server = mock_server(true);
+    let mut session = session(server.addr());
+
+    assert_eq!(session.challenge().unwrap(), Ipv4Addr::new(10, 0, 0, 7));
+    assert_eq!(session.context().data.salt, [1, 2, 3, 4]);
+    assert_eq!(session.account(), None);
+    session.login().unwrap();
+    let account = session.account().unwrap();
+    assert_eq!((account.used_minutes, account.balance_cents), (90, 1234));
+    session.keep_alive().unwrap();
+    session.keep_alive().unwrap();
+    assert_eq!(session.context().data.tail_2, [9, 9, 9, 9]);
+    assert_eq!(session.context().data.keep_alive_version, (0xdc, 0x02));
+    assert_eq!(session.rounds, 2);
+    assert_eq!(session.take_notices(), vec!["notice", "notice"]);
+    assert!(session.take_notices().is_empty());
+    session.logout().unwrap();
+  }
+
+  #[test]
+  fn test_session_forced_offline() {
+    let server = mock_server(true);
+    let mut session = session(server.addr());
+
+    let mut events = Vec::new();
+    let Err(error) = session
+      .run_with(|event| events.push(format!("{:?}", event)), |_, _| Ok(()));
+    assert!(matches!(error, AuthError::ForcedOffline(ref m) if m == "kicked"));
+    assert_eq!(session.rounds(), 2);
+    assert_eq!(events.iter().filter(|e| e.starts_with("Notice")).count(), 2);
+  }
+
+  #[test]
+  fn test_keep_alive_drops_late_replies() {
+    // every keep alive reply is preceded by the reply to the previous packet
+    let mut previous: Option<Vec<u8>> = None;
+    let server = MockServer::new(move |request| {
+      let mut replies = Vec::new();
+      if let Some(mut late) = previous.take() {
+        if let Some(tail) = late.get_mut(16..20) {
+          tail.copy_from_slice(&[6, 6, 6, 6]);
+        }
+        replies.push(late);
+      }
+      let answer = reply(request, true);
+      if let [0xff | 0x07, ..] = request {
+        previous = answer.clone();
+      }
+      replies.extend(answer);
+      replies
+    });
+    let mut session = session(server.addr());
+
+    session.challenge().unwrap();
+    session.login().unwrap();
+    for _ in 0..3 {
+      session.keep_alive().unwrap();
+      assert_eq!(session.context().data.tail_2, [9, 9, 9, 9]);
+      assert_eq!(session.context().data.keep_alive_version, (0xdc, 0x02));
+    }
+  }
+
+  #[test]
+  fn test_session_rejected() {
+    let server = mock_server(false);
+    let mut session = session(server.addr());
+
+    session.challenge().unwrap();
+    assert!(matches!(
+      session.login(),
+      Err(AuthError::InvalidUsernameOrPassword)
+    ));
+  }
+}
@@ -0,0 +1,42 @@
+use clap::Parser;
+
+use crate::auth::DrClient;
+use crate::user::args::OutputFormat;
+
+#[derive(Parser)]
+pub struct DoctorArgs {
+  /// The user authentication file to check
+  #[arg(short, long)]
+  pub file: Option<String>,
+
+  /// The user store entry to check, instead of a file (defaults to the
+  /// only entry of the store)
+  #[arg(short, long, conflicts_with = "file")]
+  pub user: Option<String>,
+
+  /// Identity (age identity file or SSH private key) for user files
+  /// encrypted to recipients
+  #[arg(short, long)]
+  pub identity: Option<String>,
+
+  /// Address of the authentication server, as `host:port`
+  #[arg(short, long, default_value = DrClient::DEFAULT_SERVER)]
+  pub server: String,
+
+  /// The interface expected to reach the server, whose MAC address should
+  /// match the user file (defaults to the interface of the route)
+  #[arg(short = 'I', long)]
+  pub interface: Option<String>,
+
+  /// Timeout for the challenge sent to the server, in seconds
+  #[arg(short, long, default_value = "2")]
+  pub timeout: u64,
+
+  /// Do not send a challenge to the server
+  #[arg(long)]
+  pub offline: bool,
+
+  /// Output format
+  #[arg(long, default_value = "text")]
+  pub format: OutputFormat,
+}
@@ -0,0 +1,386 @@
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::auth::{client::resolve, env::DrEnv, env::SystemEnv, DrClient};
+use crate::user::{perms, read_user_file, store::resolve_user_file, User};
+
+use super::args::DoctorArgs;
+
+/// The port the Dr.COM server listens on and expects clients to use.
+const AUTH_PORT: u16 = 61440;
+/// Size of the hostname field in the login packet.
+const HOSTNAME_MAX_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+  Pass,
+  Warn,
+  Fail,
+  /// Not run, for lack of what an earlier check should have found.
+  Skip,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Check {
+  pub name: &'static str,
+  pub status: Status,
+  pub message: String,
+}
+
+impl Check {
+  fn new<S: Into<String>>(
+    name: &'static str,
+    status: Status,
+    message: S,
+  ) -> Self {
+    Self {
+      name,
+      status,
+      message: message.into(),
+    }
+  }
+}
+
+/// Runs every check, in an order where later ones can use what earlier ones
+/// found.
+pub fn run(args: &DoctorArgs) -> Vec<Check> {
+  let mut checks = Vec::new();
+
+  let user = match resolve_user_file(args.file.clone(), args.user.as_deref()) {
+    Ok(file) => {
+      checks.push(check_permissions(&file));
+      let (check, user) = check_user_file(&file, args.identity.as_deref());
+      checks.push(check);
+      user
+    }
+    Err(e) => {
+      checks.push(Check::new("user file", Status::Fail, e.to_string()));
+      None
+    }
+  };
+  checks.push(check_hostname(&SystemEnv.hostname()));
+
+  let server = match resolve(&args.server) {
+    Ok(server) => {
+      checks.push(Check::new(
+        "server",
+        Status::Pass,
+        format!("{} resolves to {}", args.server, server),
+      ));
+      Some(server)
+    }
+    Err(e) => {
+      checks.push(Check::new("server", Status::Fail, e.to_string()));
+      None
+    }
+  };
+
+  let source = match server {
+    Some(server) => {
+      let (check, source) = check_route(server, args.interface.as_deref());
+      checks.push(check);
+      source
+    }
+    None => {
+      checks.push(Check::new("route", Status::Skip, "no server address"));
+      None
+    }
+  };
+  checks.push(check_port());
+
+  let interface = args
+    .interface
+    .clone()
+    .or_else(|| source.as_ref().and_then(|(_, name)| name.clone()));
+  checks.push(match (&user, interface) {
+    (Some(user), Some(interface)) => check_mac(user, &interface),
+    (None, _) => Check::new("mac", Status::Skip, "no valid user file"),
+    (_, None) => Check::new("mac", Status::Skip, "no interface to compare"),
+  });
+
+  checks.push(match (user, server) {
+    _ if args.offline => Check::new("challenge", Status::Skip, "--offline"),
+    (Some(user), Some(_)) => check_challenge(
+      user,
+      &args.server,
+      Duration::from_secs(args.timeout),
+      source.map(|(ip, _)| ip),
+    ),
+    (None, _) => Check::new("challenge", Status::Skip, "no valid user file"),
+    (_, None) => Check::new("challenge", Status::Skip, "no server address"),
+  });
+  checks
+}
+
+fn check_permissions(file: &str) -> Check {
+  let name = "permissions";
+  let issues = match perms::check_permissions(Path::new(file)) {
+    Ok(issues) => issues,
+    Err(e) => return Check::new(name, Status::Fail, e.to_string()),
+  };
+  if !issues.is_empty() {
+    let reason = issues
+      .iter()
+      .map(ToString::to_string)
+      .collect::<Vec<_>>()
+      .join(", ");
+    return Check::new(name, Status::Warn, format!("{}: {}", file, reason));
+  }
+  let message = match perms::mode_and_owner(Path::new(file)) {
+    Ok(Some((mode, uid))) => {
+      format!("{}: mode {:04o}, uid {}", file, mode, uid)
+    }
+    _ => file.to_string(),
+  };
+  Check::new(name, Status::Pass, message)
+}
+
+fn check_user_file(
+  file: &str,
+  identity: Option<&str>,
+) -> (Check, Option<User>) {
+  let name = "user file";
+  match read_user_file(file, identity) {
+    Ok((format, user)) => {
+      let message = format!(
+        "{} decrypts ({}), user {}, MAC {}",
+        file,
+        format.encryption(),
+        user.username,
+        User::format_mac(&user.mac)
+      );
+      (Check::new(name, Status::Pass, message), Some(user))
+    }
+    Err(e) => (Check::new(name, Status::Fail, e.to_string()), None),
+  }
+}
+
+pub fn check_hostname(hostname: &str) -> Check {
+  let name = "hostname";
+  if hostname.chars().any(char::is_control) {
+    return Check::new(
+      name,
+      Status::Fail,
+      format!(
+        "{:?} has control characters, which break the login packet",
+        hostname
+      ),
+    );
+  }
+  if hostname.is_empty() {
+    return Check::new(name, Status::Warn, "empty");
+  }
+  if hostname.len() > HOSTNAME_MAX_LEN {
+    return Check::new(
+      name,
+      Status::Warn,
+      format!(
+        "{} is {} bytes, cut to the {} of the login packet",
+        hostname,
+        hostname.len(),
+        HOSTNAME_MAX_LEN
+      ),
+    );
+  }
+  if !hostname.is_ascii() {
+    return Check::new(
+      name,
+      Status::Warn,
+      format!("{} is not ASCII, the server may reject it", hostname),
+    );
+  }
+  Check::new(name, Status::Pass, hostname)
+}
+
+/// Finds the source address and interface of the route to the server.
+fn check_route(
+  server: SocketAddr,
+  expected: Option<&str>,
+) -> (Check, Option<(Ipv4Addr, Option<String>)>) {
+  let name = "route";
+  // connecting a UDP socket only looks up the route
+  let source = UdpSocket::bind("0.0.0.0:0")
+    .and_then(|socket| socket.connect(server).map(|_| socket))
+    .and_then(|socket| socket.local_addr());
+  let ip = match source {
+    Ok(SocketAddr::V4(addr)) => *addr.ip(),
+    Ok(addr) => {
+      let message = format!("unexpected source address {}", addr);
+      return (Check::new(name, Status::Fail, message), None);
+    }
+    Err(e) => {
+      let message = format!("no route to {}: {}", server, e);
+      return (Check::new(name, Status::Fail, message), None);
+    }
+  };
+  let interface = interface_of(ip);
+  let via = interface.as_deref().unwrap_or("an unknown interface");
+  let check = match expected {
+    Some(expected) if interface.as_deref() != Some(expected) => Check::new(
+      name,
+      Status::Warn,
+      format!(
+        "{} is routed via {} ({}), not {}",
+        server, via, ip, expected
+      ),
+    ),
+    _ => Check::new(
+      name,
+      Status::Pass,
+      format!("{} is routed via {} ({})", server, via, ip),
+    ),
+  };
+  (check, Some((ip, interface)))
+}
+
+fn check_port() -> Check {
+  let name = "udp port";
+  match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, AUTH_PORT)) {
+    Ok(_) => Check::new(name, Status::Pass, format!("{} is free", AUTH_PORT)),
+    Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => Check::new(
+      name,
+      Status::Warn,
+      format!("{} is in use, is another client running?", AUTH_PORT),
+    ),
+    Err(e) => Check::new(
+      name,
+      Status::Warn,
+      format!("cannot bind {}: {}", AUTH_PORT, e),
+    ),
+  }
+}
+
+fn check_mac(user: &User, interface: &str) -> Check {
+  let name = "mac";
+  let mac = match User::interface_mac(interface) {
+    Ok(mac) => mac,
+    Err(e) => return Check::new(name, Status::Warn, e.to_string()),
+  };
+  let (user_mac, interface_mac) =
+    (User::format_mac(&user.mac), User::format_mac(&mac));
+  if user.mac == mac {
+    Check::new(
+      name,
+      Status::Pass,
+      format!("{} matches {}", user_mac, interface),
+    )
+  } else {
+    Check::new(
+      name,
+      Status::Warn,
+      format!(
+        "user file has {}, but {} has {}",
+        user_mac, interface, interface_mac
+      ),
+    )
+  }
+}
+
+/// Sends a challenge, which the server answers without logging anyone in.
+fn check_challenge(
+  user: User,
+  server: &str,
+  timeout: Duration,
+  source: Option<Ipv4Addr>,
+) -> Check {
+  let name = "challenge";
+  let client = DrClient::builder()
+    .server(server)
+    .timeout(timeout)
+    .challenge_tries(2)
+    .build();
+  let client_ip = match client.connect(user).and_then(|mut s| s.challenge()) {
+    Ok(client_ip) => client_ip,
+    Err(e) => return Check::new(name, Status::Fail, e.to_string()),
+  };
+  match source {
+    Some(source) if source != client_ip => Check::new(
+      name,
+      Status::Warn,
+      format!(
+        "answered, but sees client IP {} while packets leave from {}",
+        client_ip, source
+      ),
+    ),
+    _ => Check::new(
+      name,
+      Status::Pass,
+      format!("answered, client IP {}", client_ip),
+    ),
+  }
+}
+
+/// Finds the interface holding an IPv4 address.
+#[cfg(unix)]
+fn interface_of(ip: Ipv4Addr) -> Option<String> {
+  let mut addrs = std::ptr::null_mut();
+  // SAFETY: addrs is only read after getifaddrs succeeded and freed once
+  if unsafe { libc::getifaddrs(&mut addrs) } != 0 {
+    return None;
+  }
+  let mut found = None;
+  let mut cursor = addrs;
+  while !cursor.is_null() {
+    // SAFETY: cursor points into the list returned by getifaddrs
+    let ifa = unsafe { &*cursor };
+    let addr = ifa.ifa_addr;
+    if !addr.is_null()
+      // SAFETY: a non-null ifa_addr points to a sockaddr owned by the list
+      && i32::from(unsafe { (*addr).sa_family }) == libc::AF_INET
+    {
+      // SAFETY: an AF_INET address is a sockaddr_in
+      let addr = unsafe { &*(addr as *const libc::sockaddr_in) };
+      if Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)) == ip {
+        // SAFETY: ifa_name is a valid C string
+        let name = unsafe { std::ffi::CStr::from_ptr(ifa.ifa_name) };
+        found = Some(name.to_string_lossy().to_string());
+        break;
+      }
+    }
+    cursor = ifa.ifa_next;
+  }
+  // SAFETY: addrs came from getifaddrs, is freed only here and nothing
+  // borrowed from it outlives this call, the name was copied into `found`
+  unsafe { libc::freeifaddrs(addrs) };
+  found
+}
+
+#[cfg(not(unix))]
+fn interface_of(_ip: Ipv4Addr) -> Option<String> {
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_check_hostname() {
+    assert_eq!(check_hostname("router").status, Status::Pass);
+    assert_eq!(check_hostname("").status, Status::Warn);
+    assert_eq!(check_hostname(&"a".repeat(33)).status, Status::Warn);
+    assert_eq!(check_hostname("路由器").status, Status::Warn);
+    assert_eq!(check_hostname("a\0b").status, Status::Fail);
+    assert_eq!(check_hostname("a\nb").status, Status::Fail);
+  }
+
+  #[test]
+  fn test_check_route() {
+    let server = SocketAddr::from((Ipv4Addr::LOCALHOST, AUTH_PORT));
+    let (check, source) = check_route(server, None);
+    assert_eq!(check.status, Status::Pass);
+    let (ip, interface) = source.unwrap();
+    assert_eq!(ip, Ipv4Addr::LOCALHOST);
+    #[cfg(unix)]
+    assert!(interface.is_some());
+    #[cfg(not(unix))]
+    assert!(interface.is_none());
+
+    let (check, _) = check_route(server, Some("cygnus-none0"));
+    assert_eq!(check.status, Status::Warn);
+  }
+}
@@ -0,0 +1,44 @@
+//! Runs the checks we would otherwise go through by hand when a client does
+//! not work: the user file, the hostname, the route to the server and the
+//! server itself.
+
+pub mod args;
+pub mod check;
+
+use std::io;
+
+use args::DoctorArgs;
+use check::{Check, Status};
+
+use crate::user::args::OutputFormat;
+
+/// Prints the result of every check, returning whether none of them failed.
+pub fn doctor_command_resolver(args: DoctorArgs) -> io::Result<bool> {
+  let checks = check::run(&args);
+  match args.format {
+    OutputFormat::Text => print_text(&checks),
+    OutputFormat::Json => {
+      println!("{}", serde_json::to_string_pretty(&checks)?)
+    }
+  }
+  Ok(checks.iter().all(|check| check.status != Status::Fail))
+}
+
+fn print_text(checks: &[Check]) {
+  for check in checks {
+    let status = match check.status {
+      Status::Pass => "pass",
+      Status::Warn => "warn",
+      Status::Fail => "FAIL",
+      Status::Skip => "skip",
+    };
+    println!("[{}] {}: {}", status, check.name, check.message);
+  }
+  let count = |status| checks.iter().filter(|c| c.status == status).count();
+  println!(
+    "\n{} passed, {} warned, {} failed",
+    count(Status::Pass),
+    count(Status::Warn),
+    count(Status::Fail)
+  );
+}
@@ -0,0 +1,295 @@
+//! A small JSON API to watch and steer a running client from another
+//! machine.
+//!
+//! `GET /healthz` answers without credentials for liveness probes. Every
+//! other endpoint needs an `Authorization: Bearer <token>` header:
+//! `GET /status`, `GET /metrics` (in the Prometheus text format),
+//! `POST /reconnect`, `POST /logout` and `POST /login`.
+
+use std::fmt::Write;
+use std::io;
+use std::net::SocketAddr;
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{debug, info, warn};
+
+use crate::auth::{Command, Controller, State, Status};
+
+/// Where the API listens unless told otherwise.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8461";
+
+/// Serves the API on `listen` from a background thread, returning the bound
+/// address.
+pub fn serve(
+  listen: &str,
+  token: String,
+  control: Controller,
+) -> io::Result<SocketAddr> {
+  if token.is_empty() {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      "the HTTP API needs a non-empty token",
+    ));
+  }
+  let server = Server::http(listen).map_err(io::Error::other)?;
+  let addr = server
+    .server_addr()
+    .to_ip()
+    .ok_or_else(|| io::Error::other("not an IP listener"))?;
+  if !addr.ip().is_loopback() {
+    warn!(%addr, "HTTP API listening beyond localhost");
+  }
+  info!(%addr, "HTTP API listening");
+
+  std::thread::spawn(move || {
+    for request in server.incoming_requests() {
+      let (code, body) = handle(&request, &token, &control);
+      debug!(
+        method = %request.method(),
+        url = request.url(),
+        code,
+        "HTTP request"
+      );
+      let (body, content_type) = match body {
+        Body::Json(value) => (value.to_string(), "application/json"),
+        Body::Metrics(text) => (text, "text/plain; version=0.0.4"),
+      };
+      let mut response = Response::from_string(body)
+        .with_status_code(code)
+        .with_header(header("Content-Type", content_type));
+      if code == 401 {
+        response = response.with_header(header("WWW-Authenticate", "Bearer"));
+      }
+      if let Err(e) = request.respond(response) {
+        debug!("Failed to send HTTP response: {}", e);
+      }
+    }
+  });
+  Ok(addr)
+}
+
+fn header(name: &str, value: &str) -> Header {
+  Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+enum Body {
+  Json(Value),
+  Metrics(String),
+}
+
+fn handle(request: &Request, token: &str, control: &Controller) -> (u16, Body) {
+  if request.url().split('?').next() == Some("/metrics")
+    && *request.method() == Method::Get
+    && authorized(request, token)
+  {
+    return (200, Body::Metrics(metrics(&control.status())));
+  }
+  let (code, value) = handle_json(request, token, control);
+  (code, Body::Json(value))
+}
+
+fn handle_json(
+  request: &Request,
+  token: &str,
+  control: &Controller,
+) -> (u16, Value) {
+  let path = request.url().split('?').next().unwrap_or_default();
+  let method = request.method();
+  if path == "/healthz" {
+    return match method {
+      Method::Get => (200, json!({ "status": "ok" })),
+      _ => (405, json!({ "error": "method not allowed" })),
+    };
+  }
+  if !authorized(request, token) {
+    return (401, json!({ "error": "unauthorized" }));
+  }
+
+  let command = match path {
+    "/status" => {
+      return match method {
+        Method::Get => (200, json!(control.status())),
+        _ => (405, json!({ "error": "method not allowed" })),
+      };
+    }
+    // answered by `handle` when the method is right
+    "/metrics" => return (405, json!({ "error": "method not allowed" })),
+    "/reconnect" => Command::Reconnect,
+    "/logout" => Command::Logout,
+    "/login" => Command::Login,
+    _ => return (404, json!({ "error": "not found" })),
+  };
+  if *method != Method::Post {
+    return (405, json!({ "error": "method not allowed" }));
+  }
+  info!(?command, "Command from the HTTP API");
+  control.send(command);
+  (202, json!({ "accepted": path.trim_start_matches('/') }))
+}
+
+/// The status as Prometheus gauges. The account gauges are left out until
+/// a login reported the usage.
+fn metrics(status: &Status) -> String {
+  let mut gauges = vec![
+    (
+      "cygnus_online",
+      "Whether the client is online",
+      f64::from(u8::from(status.state == State::Online)),
+    ),
+    (
+      "cygnus_uptime_seconds",
+      "Time since the current session went online",
+      status.uptime.unwrap_or_default() as f64,
+    ),
+    (
+      "cygnus_keep_alive_rounds",
+      "Keep alive rounds of the current session",
+      status.rounds as f64,
+    ),
+  ];
+  if let Some(usage) = &status.usage {
+    gauges.extend([
+      (
+        "cygnus_account_used_minutes",
+        "Time online in the billing period",
+        f64::from(usage.used_minutes),
+      ),
+      (
+        "cygnus_account_used_traffic_bytes",
+        "Traffic in the billing period",
+        f64::from(usage.used_traffic_kib) * 1024.0,
+      ),
+      (
+        "cygnus_account_balance_yuan",
+        "Remaining balance of the account",
+        usage.balance(),
+      ),
+    ]);
+  }
+  let mut text = String::new();
+  for (name, help, value) in gauges {
+    let _ = writeln!(text, "# HELP {} {}.", name, help);
+    let _ = writeln!(text, "# TYPE {} gauge", name);
+    let _ = writeln!(text, "{} {}", name, value);
+  }
+  text
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+  request
+    .headers()
+    .iter()
+    .filter(|h| h.field.equiv("Authorization"))
+    .filter_map(|h| h.value.as_str().strip_prefix("Bearer "))
+    .any(|given| constant_time_eq(given.trim().as_bytes(), token.as_bytes()))
+}
+
+/// Compares without an early exit, so the time taken does not tell how much
+/// of a guessed token was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len()
+    && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::{Read, Write};
+  use std::net::TcpStream;
+
+  use super::*;
+  use crate::auth::{AccountInfo, Event};
+
+  fn request(addr: SocketAddr, head: &str) -> (u16, Value) {
+    let (code, body) = raw_request(addr, head);
+    (code, serde_json::from_str(&body).unwrap())
+  }
+
+  fn raw_request(addr: SocketAddr, head: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+      stream,
+      "{}\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+      head
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let code = response[9..12].parse().unwrap();
+    let body = response.split("\r\n\r\n").nth(1).unwrap();
+    (code, body.to_string())
+  }
+
+  #[test]
+  fn test_api() {
+    let control = Controller::new();
+    let addr = serve("127.0.0.1:0", "secret".into(), control.clone()).unwrap();
+    let auth = "\r\nAuthorization: Bearer secret";
+
+    assert_eq!(request(addr, "GET /healthz HTTP/1.1").0, 200);
+    assert_eq!(request(addr, "GET /status HTTP/1.1").0, 401);
+    let wrong = "GET /status HTTP/1.1\r\nAuthorization: Bearer secreT";
+    assert_eq!(request(addr, wrong).0, 401);
+
+    let (code, status) =
+      request(addr, &format!("GET /status HTTP/1.1{}", auth));
+    assert_eq!(code, 200);
+    assert_eq!(status["state"], "connecting");
+    assert_eq!(status["client_ip"], Value::Null);
+    assert_eq!(status["usage"], Value::Null);
+
+    assert_eq!(raw_request(addr, "GET /metrics HTTP/1.1").0, 401);
+    let (code, metrics) =
+      raw_request(addr, &format!("GET /metrics HTTP/1.1{}", auth));
+    assert_eq!(code, 200);
+    assert!(metrics.contains("\ncygnus_online 0\n"));
+    assert_eq!(
+      request(addr, &format!("POST /metrics HTTP/1.1{}", auth)).0,
+      405
+    );
+
+    let (code, body) = request(addr, &format!("POST /logout HTTP/1.1{}", auth));
+    assert_eq!((code, body["accepted"].as_str()), (202, Some("logout")));
+    assert_eq!(
+      control.wait(std::time::Duration::ZERO),
+      Some(Command::Logout)
+    );
+    assert_eq!(
+      request(addr, &format!("GET /logout HTTP/1.1{}", auth)).0,
+      405
+    );
+    assert_eq!(request(addr, &format!("GET /nope HTTP/1.1{}", auth)).0, 404);
+    assert_eq!(control.status().state, State::Connecting);
+
+    assert!(serve("127.0.0.1:0", String::new(), control).is_err());
+  }
+
+  #[test]
+  fn test_metrics() {
+    let control = Controller::new();
+    assert!(!metrics(&control.status()).contains("cygnus_account"));
+
+    let info = AccountInfo {
+      used_minutes: 90,
+      used_traffic_kib: 2048,
+      balance_cents: 1234,
+    };
+    control.observe(&Event::Account { info });
+    let text = metrics(&control.status());
+    assert!(text.starts_with(
+      "# HELP cygnus_online Whether the client is online.\n\
+       # TYPE cygnus_online gauge\n\
+       cygnus_online 0\n"
+    ));
+    assert!(text.contains("\ncygnus_account_used_minutes 90\n"));
+    assert!(text.contains("\ncygnus_account_used_traffic_bytes 2097152\n"));
+    assert!(text.contains("\ncygnus_account_balance_yuan 12.34\n"));
+  }
+
+  #[test]
+  fn test_constant_time_eq() {
+    assert!(constant_time_eq(b"token", b"token"));
+    assert!(!constant_time_eq(b"token", b"tokeN"));
+    assert!(!constant_time_eq(b"token", b"token2"));
+  }
+}
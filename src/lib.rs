@@ -1,3 +1,14 @@
+#[cfg(feature = "cli")]
 pub mod args;
 pub mod auth;
+#[cfg(feature = "cli")]
+pub mod doctor;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "cli")]
+pub mod logging;
+mod time;
 pub mod user;
+
+#[cfg(test)]
+mod testing;
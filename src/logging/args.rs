@@ -0,0 +1,123 @@
+use std::str::FromStr;
+
+use clap::{Parser, ValueEnum};
+use tracing::Level;
+
+#[derive(Parser, Debug, Clone)]
+pub struct LogArgs {
+  /// Log level
+  #[arg(short, long, global = true, default_value = "info")]
+  pub log_level: LogLevel,
+
+  /// Filter directives in `RUST_LOG` syntax (e.g. `cygnus::auth=debug`),
+  /// overrides the log level and `RUST_LOG`
+  #[arg(long, global = true)]
+  pub log_filter: Option<String>,
+
+  /// Log output format
+  #[arg(long, global = true, default_value = "full")]
+  pub log_format: LogFormat,
+
+  /// Write logs to this file instead of stderr
+  #[arg(long, global = true, conflicts_with = "journald")]
+  pub log_file: Option<String>,
+
+  /// Rotation of the log file: `never`, `daily` or a size such as `10M`
+  #[arg(long, global = true, default_value = "never", requires = "log_file")]
+  pub log_rotation: LogRotation,
+
+  /// Number of rotated log files to keep
+  #[arg(long, global = true, default_value = "7")]
+  pub log_keep: usize,
+
+  /// Send logs to the systemd journal with structured fields
+  #[arg(long, global = true)]
+  pub journald: bool,
+}
+
+#[derive(Debug, ValueEnum, Clone)]
+pub enum LogLevel {
+  Trace,
+  Debug,
+  Info,
+  Warn,
+  Error,
+}
+
+impl From<LogLevel> for Level {
+  fn from(level: LogLevel) -> Self {
+    match level {
+      LogLevel::Trace => Level::TRACE,
+      LogLevel::Debug => Level::DEBUG,
+      LogLevel::Info => Level::INFO,
+      LogLevel::Warn => Level::WARN,
+      LogLevel::Error => Level::ERROR,
+    }
+  }
+}
+
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+  Full,
+  Compact,
+  Pretty,
+  Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogRotation {
+  Never,
+  Daily,
+  /// Rotate once the file would grow past this many bytes.
+  Size(u64),
+}
+
+impl FromStr for LogRotation {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "never" => return Ok(LogRotation::Never),
+      "daily" => return Ok(LogRotation::Daily),
+      _ => {}
+    }
+    let (digits, unit) = match s.char_indices().last() {
+      Some((i, c)) if c.is_ascii_alphabetic() => (&s[..i], c),
+      _ => (s, 'B'),
+    };
+    let multiplier = match unit.to_ascii_uppercase() {
+      'B' => 1,
+      'K' => 1 << 10,
+      'M' => 1 << 20,
+      'G' => 1 << 30,
+      _ => return Err(format!("invalid size unit in `{}`", s)),
+    };
+    match digits
+      .parse::<u64>()
+      .map(|size| size.checked_mul(multiplier))
+    {
+      Ok(Some(size)) if size > 0 => Ok(LogRotation::Size(size)),
+      Ok(None) => Err(format!("size `{}` is too large", s)),
+      _ => Err(format!("expected `never`, `daily` or a size, got `{}`", s)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_rotation() {
+    assert_eq!("never".parse(), Ok(LogRotation::Never));
+    assert_eq!("daily".parse(), Ok(LogRotation::Daily));
+    assert_eq!("512".parse(), Ok(LogRotation::Size(512)));
+    assert_eq!("10M".parse(), Ok(LogRotation::Size(10 << 20)));
+    assert_eq!("1g".parse(), Ok(LogRotation::Size(1 << 30)));
+    assert!("0".parse::<LogRotation>().is_err());
+    assert!("10X".parse::<LogRotation>().is_err());
+    assert!("99999999999999999999G".parse::<LogRotation>().is_err());
+    assert!("99999999999G".parse::<LogRotation>().is_err());
+    assert!("weekly".parse::<LogRotation>().is_err());
+  }
+}
@@ -0,0 +1,16 @@
+#[derive(thiserror::Error, Debug)]
+pub enum LogError {
+  #[error("IO error -> {0}")]
+  Io(#[from] std::io::Error),
+
+  #[error("Invalid log filter -> {0}")]
+  Filter(#[from] tracing_subscriber::filter::ParseError),
+
+  #[error("Failed to set default subscriber -> {0}")]
+  Init(#[from] tracing_subscriber::util::TryInitError),
+
+  #[error("Journald is not supported on this platform")]
+  JournaldUnsupported,
+}
+
+pub type LogResult<T> = Result<T, LogError>;
@@ -0,0 +1,68 @@
+pub mod args;
+pub mod error;
+pub mod rotate;
+
+use std::sync::Mutex;
+
+use args::{LogArgs, LogFormat};
+use error::LogResult;
+use rotate::RotatingFile;
+use tracing::Level;
+use tracing_subscriber::{
+  filter::EnvFilter, fmt, layer::SubscriberExt, registry::Registry,
+  util::SubscriberInitExt, Layer,
+};
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Installs the global subscriber described by the logging arguments.
+pub fn init(args: &LogArgs) -> LogResult<()> {
+  let level: Level = args.log_level.clone().into();
+  let filter = match &args.log_filter {
+    Some(directives) => EnvFilter::try_new(directives)?,
+    None => EnvFilter::builder()
+      .with_default_directive(level.into())
+      .from_env_lossy(),
+  };
+
+  let output = if args.journald {
+    journald_layer()?
+  } else if let Some(path) = &args.log_file {
+    let file = RotatingFile::open(path, args.log_rotation, args.log_keep)?;
+    fmt_layer(args.log_format, Mutex::new(file), false)
+  } else {
+    fmt_layer(args.log_format, std::io::stderr, true)
+  };
+
+  tracing_subscriber::registry()
+    .with(output.with_filter(filter))
+    .try_init()?;
+  Ok(())
+}
+
+fn fmt_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+  W: for<'w> fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+  let layer = fmt::layer().with_writer(writer).with_ansi(ansi);
+  match format {
+    LogFormat::Full => layer.boxed(),
+    LogFormat::Compact => layer.compact().boxed(),
+    LogFormat::Pretty => layer.pretty().boxed(),
+    LogFormat::Json => layer.json().with_current_span(true).boxed(),
+  }
+}
+
+#[cfg(unix)]
+fn journald_layer() -> LogResult<BoxedLayer> {
+  Ok(
+    tracing_journald::layer()?
+      .with_syslog_identifier("cygnus".to_string())
+      .boxed(),
+  )
+}
+
+#[cfg(not(unix))]
+fn journald_layer() -> LogResult<BoxedLayer> {
+  Err(error::LogError::JournaldUnsupported)
+}
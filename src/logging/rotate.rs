@@ -0,0 +1,183 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::time::civil_from_days;
+
+use super::args::LogRotation;
+
+/// A log file that rotates by size (`cygnus.log.1`, `cygnus.log.2`, ...) or
+/// by UTC day (`cygnus.log.2024-09-01`), keeping at most `keep` old files.
+pub struct RotatingFile {
+  path: PathBuf,
+  rotation: LogRotation,
+  keep: usize,
+  file: File,
+  size: u64,
+  day: u64,
+}
+
+impl RotatingFile {
+  pub fn open<P: Into<PathBuf>>(
+    path: P,
+    rotation: LogRotation,
+    keep: usize,
+  ) -> io::Result<Self> {
+    let path = path.into();
+    let file = open_append(&path)?;
+    let metadata = file.metadata()?;
+    let day = match metadata.modified() {
+      Ok(modified) => day_of(modified),
+      Err(_) => day_of(SystemTime::now()),
+    };
+    Ok(Self {
+      path,
+      rotation,
+      keep,
+      size: metadata.len(),
+      file,
+      day,
+    })
+  }
+
+  fn should_rotate(&self, incoming: usize, today: u64) -> bool {
+    match self.rotation {
+      LogRotation::Never => false,
+      LogRotation::Daily => today != self.day,
+      LogRotation::Size(max) => {
+        self.size > 0 && self.size + incoming as u64 > max
+      }
+    }
+  }
+
+  fn rotate(&mut self, today: u64) -> io::Result<()> {
+    self.file.flush()?;
+    match self.rotation {
+      LogRotation::Never => {}
+      LogRotation::Daily => {
+        let rotated = suffixed(&self.path, &format_day(self.day));
+        std::fs::rename(&self.path, rotated)?;
+        self.prune_daily()?;
+      }
+      LogRotation::Size(_) => {
+        for i in (1..self.keep).rev() {
+          let from = suffixed(&self.path, &i.to_string());
+          if from.exists() {
+            std::fs::rename(from, suffixed(&self.path, &(i + 1).to_string()))?;
+          }
+        }
+        if self.keep == 0 {
+          std::fs::remove_file(&self.path)?;
+        } else {
+          std::fs::rename(&self.path, suffixed(&self.path, "1"))?;
+        }
+      }
+    }
+    self.file = open_append(&self.path)?;
+    self.size = 0;
+    self.day = today;
+    Ok(())
+  }
+
+  fn prune_daily(&self) -> io::Result<()> {
+    let dir = match self.path.parent() {
+      Some(dir) if !dir.as_os_str().is_empty() => dir,
+      _ => Path::new("."),
+    };
+    let prefix = match self.path.file_name() {
+      Some(name) => format!("{}.", name.to_string_lossy()),
+      None => return Ok(()),
+    };
+    let mut rotated = std::fs::read_dir(dir)?
+      .filter_map(|entry| entry.ok())
+      .map(|entry| entry.file_name().to_string_lossy().to_string())
+      .filter(|name| {
+        name
+          .strip_prefix(&prefix)
+          .is_some_and(|date| date.len() == 10 && date.as_bytes()[4] == b'-')
+      })
+      .collect::<Vec<_>>();
+    rotated.sort();
+    let excess = rotated.len().saturating_sub(self.keep);
+    for name in &rotated[..excess] {
+      std::fs::remove_file(dir.join(name))?;
+    }
+    Ok(())
+  }
+}
+
+impl Write for RotatingFile {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let today = day_of(SystemTime::now());
+    if self.should_rotate(buf.len(), today) {
+      self.rotate(today)?;
+    }
+    let written = self.file.write(buf)?;
+    self.size += written as u64;
+    Ok(written)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.file.flush()
+  }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+  OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+  let mut name = path.as_os_str().to_owned();
+  name.push(".");
+  name.push(suffix);
+  PathBuf::from(name)
+}
+
+fn day_of(time: SystemTime) -> u64 {
+  time
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs() / 86400)
+    .unwrap_or_default()
+}
+
+/// Formats days since the Unix epoch as `YYYY-MM-DD`.
+fn format_day(day: u64) -> String {
+  let (y, m, d) = civil_from_days(day as i64);
+  format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::temp_path;
+
+  #[test]
+  fn test_format_day() {
+    assert_eq!(format_day(0), "1970-01-01");
+    assert_eq!(format_day(19967), "2024-09-01");
+    assert_eq!(format_day(11016), "2000-02-29");
+  }
+
+  #[test]
+  fn test_size_rotation() {
+    let dir = temp_path("log");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cygnus.log");
+
+    let mut file = RotatingFile::open(&path, LogRotation::Size(8), 2).unwrap();
+    for line in ["aaaaaa\n", "bbbbbb\n", "cccccc\n", "dddddd\n"] {
+      file.write_all(line.as_bytes()).unwrap();
+    }
+    file.flush().unwrap();
+
+    let read = |suffix: &str| std::fs::read_to_string(suffixed(&path, suffix));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "dddddd\n");
+    assert_eq!(read("1").unwrap(), "cccccc\n");
+    assert_eq!(read("2").unwrap(), "bbbbbb\n");
+    assert!(read("3").is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
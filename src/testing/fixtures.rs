@@ -0,0 +1,139 @@
+//! Fixtures that only need std, so the integration tests include this file
+//! too.
+
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// How often the server thread checks whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// `cygnus-<name>-<pid>` in the temporary directory, so concurrent test runs
+/// do not share files.
+pub fn temp_path(name: &str) -> PathBuf {
+  std::env::temp_dir().join(format!("cygnus-{}-{}", name, std::process::id()))
+}
+
+/// A Dr.COM server on localhost, sending back the datagrams its handler
+/// returns for each request. It is stopped and joined when dropped.
+pub struct MockServer {
+  addr: String,
+  stop: Arc<AtomicBool>,
+  handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+  pub fn new<H, R>(mut handler: H) -> Self
+  where
+    H: FnMut(&[u8]) -> R + Send + 'static,
+    R: IntoIterator<Item = Vec<u8>>,
+  {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(POLL_INTERVAL)).unwrap();
+    let addr = socket.local_addr().unwrap().to_string();
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    let handle = std::thread::spawn(move || {
+      let mut buf = [0; 512];
+      while !stopped.load(Ordering::SeqCst) {
+        let (len, peer) = match socket.recv_from(&mut buf) {
+          Ok(received) => received,
+          Err(e)
+            if matches!(
+              e.kind(),
+              ErrorKind::WouldBlock | ErrorKind::TimedOut
+            ) =>
+          {
+            continue
+          }
+          Err(e) => panic!("mock server failed: {}", e),
+        };
+        for reply in handler(&buf[..len]) {
+          socket.send_to(&reply, peer).unwrap();
+        }
+      }
+    });
+    Self {
+      addr,
+      stop,
+      handle: Some(handle),
+    }
+  }
+
+  /// Answers every request with [`reply`], accepting logins.
+  pub fn accepting() -> Self {
+    Self::new(|request| reply(request, true))
+  }
+
+  /// Answers every request with [`reply`], refusing logins.
+  pub fn rejecting() -> Self {
+    Self::new(|request| reply(request, false))
+  }
+
+  /// The address to give the client, `127.0.0.1:<port>`.
+  pub fn addr(&self) -> &str {
+    &self.addr
+  }
+}
+
+impl Drop for MockServer {
+  fn drop(&mut self) {
+    self.stop.store(true, Ordering::SeqCst);
+    if let Some(handle) = self.handle.take() {
+      let _ = handle.join();
+    }
+  }
+}
+
+/// What the server answers to `request`, if anything. The challenge assigns
+/// 10.0.0.7 with the salt `01020304`, an accepted login reports 90 minutes
+/// used and a balance of 12.34, and a refused login has the code of a wrong
+/// password.
+pub fn reply(request: &[u8], accept: bool) -> Option<Vec<u8>> {
+  let mut reply = vec![0; 64];
+  match request.first()? {
+    0x01 => {
+      reply[0] = 0x02;
+      reply[4..8].copy_from_slice(&[1, 2, 3, 4]);
+      reply[20..24].copy_from_slice(&[10, 0, 0, 7]);
+    }
+    0x03 if accept => {
+      reply[0] = 0x04;
+      reply[5..9].copy_from_slice(&90u32.to_le_bytes());
+      reply[13..17].copy_from_slice(&1234u32.to_le_bytes());
+    }
+    0x03 => {
+      reply[0] = 0x05;
+      reply[4] = 0x03;
+    }
+    0x06 => reply[0] = 0x04,
+    0xff => {
+      reply[0] = 0x07;
+      reply[28..30].copy_from_slice(&[0xdc, 0x02]);
+    }
+    // the extra 40 byte packet gets a shorter "file" reply
+    0x07 if request.get(6..8) == Some(&[0x0f, 0x27]) => {
+      reply.truncate(16);
+      reply[..4].copy_from_slice(&[0x07, request[1], 0x10, 0x00]);
+    }
+    // a 40 byte reply echoes the counter and moves to the next step
+    0x07 if request.len() >= 6 => {
+      reply.truncate(40);
+      reply[..6].copy_from_slice(&[
+        0x07,
+        request[1],
+        0x28,
+        0x00,
+        0x0b,
+        request[5] + 1,
+      ]);
+      reply[16..20].copy_from_slice(&[9, 9, 9, 9]);
+    }
+    _ => return None,
+  }
+  Some(reply)
+}
@@ -0,0 +1,20 @@
+//! Shared test fixtures.
+
+mod fixtures;
+
+pub use fixtures::{reply, temp_path, MockServer};
+
+use crate::user::{provider::CredentialProvider, User, UserResult};
+
+/// Credentials for `username` with a fixed password and MAC.
+pub struct FixedUser(pub &'static str);
+
+impl CredentialProvider for FixedUser {
+  fn load(&self) -> UserResult<User> {
+    User::new(self.0.into(), "password".into(), [0, 1, 2, 3, 4, 5])
+  }
+
+  fn describe(&self) -> String {
+    format!("fixed {}", self.0)
+  }
+}
@@ -0,0 +1,35 @@
+//! Calendar arithmetic shared by schedules, budgets and log rotation.
+
+/// Converts days since the Unix epoch to a `(year, month, day)` date.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  // civil_from_days from http://howardhinnant.github.io/date_algorithms.html
+  let z = days + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z.rem_euclid(146097);
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let d = doy - (153 * mp + 2) / 5 + 1;
+  let m = if mp < 10 { mp + 3 } else { mp - 9 };
+  let y = yoe + era * 400 + i64::from(m <= 2);
+  (y, m as u32, d as u32)
+}
+
+/// The offset of local time from UTC in seconds at `secs` since the Unix
+/// epoch.
+#[cfg(unix)]
+pub fn utc_offset(secs: i64) -> i64 {
+  // SAFETY: tm is plain data and localtime_r only writes to it
+  let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+  let time = secs as libc::time_t;
+  // SAFETY: both pointers are valid for the duration of the call
+  if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+    return 0;
+  }
+  tm.tm_gmtoff as i64
+}
+
+#[cfg(not(unix))]
+pub fn utc_offset(_secs: i64) -> i64 {
+  0
+}
@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 pub struct UserArgs {
@@ -13,6 +13,24 @@ pub enum UserCommand {
 
   /// Inspect an existing user authentication file
   Inspect(UserInspectArgs),
+
+  /// Change fields of an existing user authentication file in place
+  Edit(UserEditArgs),
+
+  /// Check that a user authentication file decrypts and is valid
+  Verify(UserVerifyArgs),
+
+  /// List the users in the user store
+  List,
+
+  /// Remove a user from the user store
+  Remove(UserRemoveArgs),
+}
+
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+  Text,
+  Json,
 }
 
 #[derive(Parser)]
@@ -21,22 +39,188 @@ pub struct UserCreateArgs {
   #[arg(short, long)]
   pub username: String,
 
-  /// The password to use
-  #[arg(short, long)]
-  pub password: String,
+  /// Prompt for the password, as without this option; a value is used as
+  /// the password (deprecated: visible in shell history and `ps`)
+  #[arg(short, long, value_name = "PASSWORD")]
+  pub password: Option<Option<String>>,
+
+  /// Read the password from the first line of standard input
+  #[arg(long, conflicts_with = "password")]
+  pub password_stdin: bool,
 
   /// The MAC address to use
   #[arg(short, long)]
   pub mac: String,
 
   /// The file to write the user authentication to
+  #[arg(short, long, required_unless_present = "name")]
+  pub file: Option<String>,
+
+  /// Store the user under this name in the user store instead of a file
+  #[arg(short, long, conflicts_with = "file")]
+  pub name: Option<String>,
+
+  /// The owner of the created file, as a user name or uid
+  #[arg(long)]
+  pub owner: Option<String>,
+
+  /// The group of the created file, as a group name or gid
+  #[arg(long)]
+  pub group: Option<String>,
+
+  /// Overwrite the file if it already exists
+  #[arg(long)]
+  pub force: bool,
+
+  /// Encrypt the file to an age or SSH public key (or a file of such keys),
+  /// so only the matching identity can read it; can be repeated
   #[arg(short, long)]
-  pub file: String,
+  pub recipient: Vec<String>,
 }
 
 #[derive(Parser)]
 pub struct UserInspectArgs {
   /// The user authentication file to inspect
   #[arg(short, long)]
-  pub file: String,
+  pub file: Option<String>,
+
+  /// The user store entry to inspect, instead of a file (defaults to the
+  /// only entry of the store)
+  #[arg(short, long, conflicts_with = "file")]
+  pub name: Option<String>,
+
+  /// Refuse files readable by group or others or owned by another user
+  #[arg(long)]
+  pub strict_perms: bool,
+
+  /// Output format
+  #[arg(long, default_value = "text")]
+  pub format: OutputFormat,
+
+  /// Also print the stored password, after confirmation
+  #[arg(long)]
+  pub show_password: bool,
+
+  /// Identity (age identity file or SSH private key) for user files
+  /// encrypted to recipients
+  #[arg(long)]
+  pub identity: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct UserVerifyArgs {
+  /// The user authentication file to verify
+  #[arg(short, long)]
+  pub file: Option<String>,
+
+  /// The user store entry to verify, instead of a file (defaults to the
+  /// only entry of the store)
+  #[arg(short, long, conflicts_with = "file")]
+  pub name: Option<String>,
+
+  /// Refuse files readable by group or others or owned by another user
+  #[arg(long)]
+  pub strict_perms: bool,
+
+  /// Identity (age identity file or SSH private key) for user files
+  /// encrypted to recipients
+  #[arg(long)]
+  pub identity: Option<String>,
+}
+
+#[derive(Parser)]
+#[command(group(
+  ArgGroup::new("changes")
+    .required(true)
+    .multiple(true)
+    .args([
+      "username",
+      "password",
+      "password_stdin",
+      "mac",
+      "interface",
+      "recipient",
+    ]),
+))]
+pub struct UserEditArgs {
+  /// The user authentication file to edit
+  #[arg(short, long)]
+  pub file: Option<String>,
+
+  /// The user store entry to edit, instead of a file (defaults to the
+  /// only entry of the store)
+  #[arg(short, long, conflicts_with = "file")]
+  pub name: Option<String>,
+
+  /// The new username
+  #[arg(short, long)]
+  pub username: Option<String>,
+
+  /// Prompt for a new password; a value is used as the password
+  /// (deprecated: visible in shell history and `ps`)
+  #[arg(short, long, value_name = "PASSWORD")]
+  pub password: Option<Option<String>>,
+
+  /// Read the new password from the first line of standard input
+  #[arg(long, conflicts_with = "password")]
+  pub password_stdin: bool,
+
+  /// The new MAC address
+  #[arg(short, long)]
+  pub mac: Option<String>,
+
+  /// Take the new MAC address from a network interface
+  #[arg(short, long, conflicts_with = "mac")]
+  pub interface: Option<String>,
+
+  /// Refuse files readable by group or others or owned by another user
+  #[arg(long)]
+  pub strict_perms: bool,
+
+  /// Identity (age identity file or SSH private key) for user files
+  /// encrypted to recipients
+  #[arg(long)]
+  pub identity: Option<String>,
+
+  /// Re-encrypt the file to an age or SSH public key (or a file of such
+  /// keys); required when the file is encrypted to recipients
+  #[arg(short, long)]
+  pub recipient: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct UserRemoveArgs {
+  /// The user store entry to remove
+  pub name: String,
+
+  /// Do not ask for confirmation
+  #[arg(short, long)]
+  pub yes: bool,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_password_option() {
+    // `-p` prompts and `-p <value>` sets the password, in both commands
+    let create = |args: &[&str]| {
+      let base = ["create", "-u", "user", "-m", "00:11:22:33:44:55", "-f", "u"];
+      UserCreateArgs::try_parse_from([&base[..], args].concat())
+        .unwrap()
+        .password
+    };
+    assert_eq!(create(&[]), None);
+    assert_eq!(create(&["-p"]), Some(None));
+    assert_eq!(create(&["-p", "secret"]), Some(Some("secret".to_string())));
+
+    let edit = |args: &[&str]| {
+      UserEditArgs::try_parse_from([&["edit", "-f", "u"][..], args].concat())
+        .unwrap()
+        .password
+    };
+    assert_eq!(edit(&["-p", "-m", "00:11:22:33:44:55"]), Some(None));
+    assert_eq!(edit(&["-p", "secret"]), Some(Some("secret".to_string())));
+  }
 }
@@ -1,24 +1,62 @@
 use aes_gcm::{aead::Aead, AeadCore, Aes256Gcm, Key, KeyInit, Nonce};
-use rand::rngs::OsRng;
-use std::io::{BufReader, BufWriter, Read, Write};
+use rand::{rngs::OsRng, RngCore};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use zeroize::Zeroizing;
 
 use super::data::User;
-use super::error::UserResult;
+use super::error::{UserError, UserResult};
+
+/// On-disk layout of a user file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserFileFormat {
+  /// The password is encrypted with a key stored alongside it.
+  Embedded,
+  /// The whole file is an age file encrypted to age or SSH recipients.
+  Recipient,
+}
+
+impl UserFileFormat {
+  const AGE_MAGIC: &'static [u8] = b"age-encryption.org/";
+
+  /// Detects the format from the first bytes of a file.
+  pub fn detect(header: &[u8]) -> Self {
+    if header.starts_with(Self::AGE_MAGIC) {
+      UserFileFormat::Recipient
+    } else {
+      UserFileFormat::Embedded
+    }
+  }
+
+  pub fn version(self) -> u8 {
+    match self {
+      UserFileFormat::Embedded => 1,
+      UserFileFormat::Recipient => 2,
+    }
+  }
+
+  pub fn encryption(self) -> &'static str {
+    match self {
+      UserFileFormat::Embedded => "aes-256-gcm, key stored in file",
+      UserFileFormat::Recipient => "age, encrypted to recipients",
+    }
+  }
+}
 
 pub struct UserCipher;
 
 impl UserCipher {
   pub fn encrypt<W: Write>(buffer: W, user: User) -> UserResult<()> {
-    let key = Aes256Gcm::generate_key(OsRng);
+    let mut key = Zeroizing::new([0u8; 32]);
+    OsRng.fill_bytes(key.as_mut());
     let nonce = Aes256Gcm::generate_nonce(OsRng);
-    let cipher = Aes256Gcm::new(&key);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_ref()));
 
     let encrypted_password =
       cipher.encrypt(&nonce, user.password.as_bytes().as_ref())?;
 
     let mut writer = BufWriter::new(buffer);
 
-    writer.write_all(&key)?;
+    writer.write_all(key.as_ref())?;
     writer.write_all(&nonce)?;
 
     writer.write_all(&(encrypted_password.len() as u64).to_be_bytes())?;
@@ -34,12 +72,78 @@ impl UserCipher {
     Ok(())
   }
 
+  /// Encrypts a user file that only the holders of the identities matching
+  /// `recipients` can read.
+  pub fn encrypt_to_recipients<W: Write>(
+    buffer: W,
+    user: User,
+    recipients: &[Box<dyn age::Recipient + Send>],
+  ) -> UserResult<()> {
+    let encryptor = age::Encryptor::with_recipients(
+      recipients.iter().map(|r| r.as_ref() as &dyn age::Recipient),
+    )?;
+
+    let mut payload = Zeroizing::new(Vec::new());
+    payload.extend_from_slice(&(user.username.len() as u64).to_be_bytes());
+    payload.extend_from_slice(user.username.as_bytes());
+    payload.extend_from_slice(&(user.password.len() as u64).to_be_bytes());
+    payload.extend_from_slice(user.password.as_bytes());
+    payload.extend_from_slice(&user.mac);
+
+    let mut writer = encryptor.wrap_output(BufWriter::new(buffer))?;
+    writer.write_all(&payload)?;
+    writer.finish()?.flush()?;
+
+    Ok(())
+  }
+
   pub fn decrypt<R: Read>(buffer: R) -> UserResult<User> {
+    Self::decrypt_with_identities(buffer, &[])
+  }
+
+  /// Decrypts a user file in either format. `identities` are only needed
+  /// for files encrypted to recipients.
+  pub fn decrypt_with_identities<R: Read>(
+    buffer: R,
+    identities: &[Box<dyn age::Identity>],
+  ) -> UserResult<User> {
     let mut reader = BufReader::new(buffer);
+    match UserFileFormat::detect(reader.fill_buf()?) {
+      UserFileFormat::Embedded => Self::decrypt_embedded(reader),
+      UserFileFormat::Recipient => Self::decrypt_recipient(reader, identities),
+    }
+  }
+
+  fn decrypt_recipient<R: BufRead>(
+    reader: R,
+    identities: &[Box<dyn age::Identity>],
+  ) -> UserResult<User> {
+    if identities.is_empty() {
+      return Err(UserError::IdentityRequired);
+    }
+    let decryptor = age::Decryptor::new_buffered(reader)?;
+    let mut payload = Zeroizing::new(Vec::new());
+    decryptor
+      .decrypt(identities.iter().map(|i| i.as_ref()))?
+      .read_to_end(&mut payload)?;
 
-    let mut key = [0u8; 32];
-    reader.read_exact(&mut key)?;
-    let key = Key::<Aes256Gcm>::from_slice(&key);
+    let mut payload = payload.as_slice();
+    let username = read_field(&mut payload)?;
+    let password = Zeroizing::new(read_field(&mut payload)?);
+    let mut mac = [0u8; 6];
+    payload.read_exact(&mut mac)?;
+
+    Ok(User::stored(
+      String::from_utf8(username)?,
+      std::str::from_utf8(&password)?.into(),
+      mac,
+    ))
+  }
+
+  fn decrypt_embedded<R: Read>(mut reader: R) -> UserResult<User> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    reader.read_exact(key.as_mut())?;
+    let key = Key::<Aes256Gcm>::from_slice(key.as_ref());
 
     let mut nonce = [0u8; 12];
     reader.read_exact(&mut nonce)?;
@@ -62,24 +166,38 @@ impl UserCipher {
     let mut mac = [0u8; 6];
     reader.read_exact(&mut mac)?;
 
-    let cipher = Aes256Gcm::new(&key);
-    let password = cipher.decrypt(&nonce, encrypted_password.as_ref())?;
+    let cipher = Aes256Gcm::new(key);
+    let password =
+      Zeroizing::new(cipher.decrypt(nonce, encrypted_password.as_ref())?);
 
-    Ok(User::new(
+    Ok(User::stored(
       String::from_utf8(username)?,
-      String::from_utf8(password)?,
+      std::str::from_utf8(&password)?.into(),
       mac,
     ))
   }
 }
 
+fn read_field(payload: &mut &[u8]) -> UserResult<Vec<u8>> {
+  let mut size_bytes = [0u8; 8];
+  payload.read_exact(&mut size_bytes)?;
+  let size = u64::from_be_bytes(size_bytes) as usize;
+  if size > payload.len() {
+    return Err(UserError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+  }
+  let (field, rest) = payload.split_at(size);
+  *payload = rest;
+  Ok(field.to_vec())
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
 
   #[test]
   fn test_encrypt_decrypt() {
-    let user = User::new("user".to_string(), "password".to_string(), [0; 6]);
+    let user =
+      User::new("user".to_string(), "password".into(), [0; 6]).unwrap();
     let mut buffer = Vec::new();
 
     UserCipher::encrypt(&mut buffer, user.clone()).unwrap();
@@ -87,4 +205,54 @@ mod tests {
 
     assert_eq!(user, decrypted_user);
   }
+
+  #[test]
+  fn test_decrypt_invalid_user() {
+    // written before the limits were enforced
+    let user = User {
+      username: "user".to_string(),
+      password: "p".repeat(20).as_str().into(),
+      mac: [0; 6],
+    };
+    let mut buffer = Vec::new();
+
+    UserCipher::encrypt(&mut buffer, user.clone()).unwrap();
+    let decrypted_user = UserCipher::decrypt(buffer.as_slice()).unwrap();
+
+    assert_eq!(user, decrypted_user);
+    assert!(matches!(
+      decrypted_user.validate(),
+      Err(UserError::PasswordLength(20))
+    ));
+  }
+
+  #[test]
+  fn test_encrypt_decrypt_recipient() {
+    let user =
+      User::new("user".to_string(), "password".into(), [1; 6]).unwrap();
+    let identity = age::x25519::Identity::generate();
+    let recipients: Vec<Box<dyn age::Recipient + Send>> =
+      vec![Box::new(identity.to_public())];
+    let mut buffer = Vec::new();
+
+    UserCipher::encrypt_to_recipients(&mut buffer, user.clone(), &recipients)
+      .unwrap();
+    assert_eq!(UserFileFormat::detect(&buffer), UserFileFormat::Recipient);
+    assert!(matches!(
+      UserCipher::decrypt(buffer.as_slice()),
+      Err(UserError::IdentityRequired)
+    ));
+
+    let identities: Vec<Box<dyn age::Identity>> = vec![Box::new(identity)];
+    let decrypted_user =
+      UserCipher::decrypt_with_identities(buffer.as_slice(), &identities)
+        .unwrap();
+    assert_eq!(user, decrypted_user);
+
+    let other: Vec<Box<dyn age::Identity>> =
+      vec![Box::new(age::x25519::Identity::generate())];
+    assert!(
+      UserCipher::decrypt_with_identities(buffer.as_slice(), &other).is_err()
+    );
+  }
 }
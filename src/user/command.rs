@@ -0,0 +1,122 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use tracing::{debug, warn};
+
+use super::args::{UserArgs, UserCommand, UserCreateArgs, UserEditArgs};
+use super::cipher::UserFileFormat;
+use super::error::{UserError, UserResult};
+use super::{
+  encrypt_user, inspect, perms, prompt, read_user_file, store, Password, User,
+};
+
+pub fn user_command_resolver(args: UserArgs) -> UserResult<()> {
+  match args.command {
+    UserCommand::Create(create_args) => create_user(create_args)?,
+    UserCommand::Inspect(inspect_args) => inspect::inspect(inspect_args)?,
+    UserCommand::Edit(edit_args) => edit_user(edit_args)?,
+    UserCommand::Verify(verify_args) => inspect::verify(verify_args)?,
+    UserCommand::List => {
+      let store = store::UserStore::open_default()?;
+      for name in store.list()? {
+        println!("{}", name);
+      }
+    }
+    UserCommand::Remove(remove_args) => {
+      let store = store::UserStore::open_default()?;
+      let path = store.existing_path(&remove_args.name)?;
+      if !remove_args.yes
+        && !prompt::confirm(&format!("Remove {}?", path.display()))?
+      {
+        return Err(UserError::Declined);
+      }
+      store.remove(&remove_args.name)?;
+      println!("User removed: {}", remove_args.name);
+    }
+  }
+  Ok(())
+}
+
+/// The password of `--password`, prompted for unless it has a value.
+fn password_option(value: Option<String>) -> UserResult<Password> {
+  match value {
+    Some(password) => {
+      warn!(
+        "A --password value is deprecated and leaks into shell history \
+         and process lists, omit it to be prompted instead"
+      );
+      Ok(password.into())
+    }
+    None => prompt::prompt_new_password(),
+  }
+}
+
+fn create_user(create_args: UserCreateArgs) -> UserResult<()> {
+  let mac = User::transform_mac(&create_args.mac)?;
+  let file = match (create_args.file, create_args.name) {
+    (Some(file), _) => file,
+    (None, Some(name)) => {
+      let store = store::UserStore::open_default()?;
+      store.ensure_dir()?;
+      store.path(&name)?.to_string_lossy().to_string()
+    }
+    (None, None) => unreachable!("clap requires --file or --name"),
+  };
+  // checked before prompting, the file is still created exclusively below
+  if !create_args.force && Path::new(&file).exists() {
+    let message = format!("{} exists, pass --force to replace it", file);
+    return Err(io::Error::new(io::ErrorKind::AlreadyExists, message).into());
+  }
+  let password = match create_args.password {
+    Some(value) => password_option(value)?,
+    None if create_args.password_stdin => prompt::read_password_stdin()?,
+    None => prompt::prompt_new_password()?,
+  };
+  let user = User::new(create_args.username, password, mac)?;
+  let user_hash = user.username_hash();
+  let buffer = encrypt_user(user, &create_args.recipient)?;
+  if create_args.force {
+    perms::write_private_atomic(&file, &buffer)?;
+  } else {
+    perms::create_private(&file)?.write_all(&buffer)?;
+  }
+  perms::set_owner(
+    &file,
+    create_args.owner.as_deref(),
+    create_args.group.as_deref(),
+  )?;
+  debug!(file, user = %user_hash, "Wrote user file");
+  println!("User file created: {}", file);
+  Ok(())
+}
+
+fn edit_user(args: UserEditArgs) -> UserResult<()> {
+  let file = store::resolve_user_file(args.file, args.name.as_deref())?;
+  for issue in perms::verify_permissions(&file, args.strict_perms)? {
+    warn!("User file {}: {}", file, issue);
+  }
+  let (format, mut user) = read_user_file(&file, args.identity.as_deref())?;
+  if format == UserFileFormat::Recipient && args.recipient.is_empty() {
+    return Err(UserError::RecipientRequired);
+  }
+
+  if let Some(username) = args.username {
+    user.username = username;
+  }
+  if let Some(value) = args.password {
+    user.password = password_option(value)?;
+  } else if args.password_stdin {
+    user.password = prompt::read_password_stdin()?;
+  }
+  if let Some(mac) = args.mac {
+    user.mac = User::transform_mac(&mac)?;
+  } else if let Some(interface) = args.interface {
+    user.mac = User::interface_mac(&interface)?;
+  }
+  user.validate()?;
+
+  let buffer = encrypt_user(user, &args.recipient)?;
+  perms::write_private_atomic(&file, &buffer)?;
+  println!("User file updated: {}", file);
+  Ok(())
+}
@@ -1,14 +1,40 @@
-use super::error::UserResult;
+use super::error::{UserError, UserResult};
+use super::secret::Password;
+
+/// Size of the username field in the login packet.
+pub const USERNAME_MAX_LEN: usize = 36;
+/// Size of the obfuscated password field in the login packet.
+pub const PASSWORD_MAX_LEN: usize = 16;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct User {
   pub username: String,
-  pub password: String,
+  pub password: Password,
   pub mac: [u8; 6],
 }
 
 impl User {
-  pub fn new(username: String, password: String, mac: [u8; 6]) -> Self {
+  pub fn new(
+    username: String,
+    password: Password,
+    mac: [u8; 6],
+  ) -> UserResult<Self> {
+    let user = Self {
+      username,
+      password,
+      mac,
+    };
+    user.validate()?;
+    Ok(user)
+  }
+
+  /// Builds a user as it was stored, without [`validate`](Self::validate),
+  /// so files that break the limits can still be inspected and edited.
+  pub(crate) fn stored(
+    username: String,
+    password: Password,
+    mac: [u8; 6],
+  ) -> Self {
     Self {
       username,
       password,
@@ -16,13 +42,126 @@ impl User {
     }
   }
 
+  pub fn validate(&self) -> UserResult<()> {
+    let username_len = self.username.len();
+    if username_len == 0 || username_len > USERNAME_MAX_LEN {
+      return Err(UserError::UsernameLength(username_len));
+    }
+    if self.username.contains('\0') {
+      return Err(UserError::UsernameNul);
+    }
+    let password_len = self.password.len();
+    if password_len == 0 || password_len > PASSWORD_MAX_LEN {
+      return Err(UserError::PasswordLength(password_len));
+    }
+    Ok(())
+  }
+
   pub fn transform_mac(mac: &str) -> UserResult<[u8; 6]> {
     let mut mac_bytes = [0u8; 6];
-    let mut i = 0;
-    for byte in mac.split(':') {
+    let parts = mac.split(':').collect::<Vec<_>>();
+    if parts.len() != mac_bytes.len()
+      || parts.iter().any(|part| part.len() != 2)
+    {
+      return Err(UserError::MacFormat(mac.to_string()));
+    }
+    for (i, byte) in parts.into_iter().enumerate() {
       mac_bytes[i] = u8::from_str_radix(byte, 16)?;
-      i += 1;
     }
     Ok(mac_bytes)
   }
+
+  /// A stable pseudonym of the username for logs.
+  pub fn username_hash(&self) -> String {
+    let digest = md5::compute(self.username.as_bytes());
+    digest.0[..6].iter().map(|b| format!("{:02x}", b)).collect()
+  }
+
+  pub fn format_mac(mac: &[u8; 6]) -> String {
+    mac
+      .iter()
+      .map(|byte| format!("{:02x}", byte))
+      .collect::<Vec<_>>()
+      .join(":")
+  }
+
+  /// Reads the hardware address of a network interface.
+  #[cfg(target_os = "linux")]
+  pub fn interface_mac(interface: &str) -> UserResult<[u8; 6]> {
+    let path = format!("/sys/class/net/{}/address", interface);
+    let address = std::fs::read_to_string(path)
+      .map_err(|_| UserError::UnknownInterface(interface.to_string()))?;
+    Self::transform_mac(address.trim())
+  }
+
+  #[cfg(not(target_os = "linux"))]
+  pub fn interface_mac(interface: &str) -> UserResult<[u8; 6]> {
+    Err(UserError::UnknownInterface(interface.to_string()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_validate() {
+    let mac = [0; 6];
+    assert!(User::new("user".into(), "password".into(), mac).is_ok());
+    assert!(User::new("u".repeat(36), "p".repeat(16).into(), mac).is_ok());
+    assert!(matches!(
+      User::new("".into(), "password".into(), mac),
+      Err(UserError::UsernameLength(0))
+    ));
+    assert!(matches!(
+      User::new("u".repeat(37), "password".into(), mac),
+      Err(UserError::UsernameLength(37))
+    ));
+    assert!(matches!(
+      User::new("us\0er".into(), "password".into(), mac),
+      Err(UserError::UsernameNul)
+    ));
+    assert!(matches!(
+      User::new("user".into(), "".into(), mac),
+      Err(UserError::PasswordLength(0))
+    ));
+    assert!(matches!(
+      User::new("user".into(), "p".repeat(17).into(), mac),
+      Err(UserError::PasswordLength(17))
+    ));
+  }
+
+  #[test]
+  fn test_debug_redacts_password() {
+    let user = User::new("user".into(), "hunter2".into(), [0; 6]).unwrap();
+    assert!(!format!("{:?}", user).contains("hunter2"));
+  }
+
+  #[test]
+  fn test_transform_mac() {
+    assert_eq!(
+      User::transform_mac("00:1a:2B:3c:4d:ff").unwrap(),
+      [0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0xff]
+    );
+    assert!(matches!(
+      User::transform_mac("00:11:22:33:44"),
+      Err(UserError::MacFormat(_))
+    ));
+    assert!(matches!(
+      User::transform_mac("00:11:22:33:44:55:66"),
+      Err(UserError::MacFormat(_))
+    ));
+    assert!(matches!(
+      User::transform_mac("00:11:22:33:44:5"),
+      Err(UserError::MacFormat(_))
+    ));
+    assert!(matches!(
+      User::transform_mac("00:11:22:33:44:zz"),
+      Err(UserError::Mac(_))
+    ));
+    assert_eq!(
+      User::format_mac(&[0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0xff]),
+      "00:1a:2b:3c:4d:ff"
+    );
+  }
 }
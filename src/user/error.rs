@@ -8,11 +8,89 @@ pub enum UserError {
   #[error("Aead error -> {0}")]
   Aead(#[from] AeadError),
 
+  #[error("Age encryption error -> {0}")]
+  AgeEncrypt(#[from] age::EncryptError),
+
+  #[error("Age decryption error -> {0}")]
+  AgeDecrypt(#[from] age::DecryptError),
+
+  #[error("Invalid recipient -> {0}, expected an age or SSH public key")]
+  InvalidRecipient(String),
+
+  #[error("The user file is encrypted to recipients, pass --identity")]
+  IdentityRequired,
+
+  #[error("The user file is encrypted to recipients, pass --recipient")]
+  RecipientRequired,
+
+  #[error("Invalid identity -> {0}")]
+  Identity(String),
+
   #[error("Invalid UTF-8 -> {0}")]
   Utf8(#[from] std::string::FromUtf8Error),
 
+  #[error("Invalid UTF-8 -> {0}")]
+  Utf8Str(#[from] std::str::Utf8Error),
+
   #[error("Invalid MAC address -> {0}")]
   Mac(#[from] std::num::ParseIntError),
+
+  #[error("Invalid MAC address -> {0}, expected 6 colon-separated hex bytes")]
+  MacFormat(String),
+
+  #[error("Unknown network interface -> {0}")]
+  UnknownInterface(String),
+
+  #[error("Invalid username length -> {0} bytes, expected 1 to 36")]
+  UsernameLength(usize),
+
+  #[error("Invalid username -> contains a NUL character")]
+  UsernameNul,
+
+  #[error("Invalid password length -> {0} bytes, expected 1 to 16")]
+  PasswordLength(usize),
+
+  #[error("Cannot prompt for password -> {0}, use --password-stdin instead")]
+  Prompt(std::io::Error),
+
+  #[error("Operation not confirmed, it needs an interactive terminal")]
+  NotConfirmed,
+
+  #[error("Operation declined")]
+  Declined,
+
+  #[error("JSON error -> {0}")]
+  Json(#[from] serde_json::Error),
+
+  #[error("Passwords do not match")]
+  PasswordMismatch,
+
+  #[error("Unknown owner -> {0}")]
+  UnknownOwner(String),
+
+  #[error("Unknown group -> {0}")]
+  UnknownGroup(String),
+
+  #[error("Cannot locate the user store, set $CYGNUS_STORE or $HOME")]
+  NoStoreDir,
+
+  #[error("Invalid user name -> {0}, use letters, digits, '-', '_' and '.'")]
+  InvalidName(String),
+
+  #[error("Unknown user -> {0}")]
+  UnknownUser(String),
+
+  #[error("No user given and the user store at {1} has {0} users")]
+  NoDefaultUser(usize, String),
+
+  #[error("Missing credential -> {0}")]
+  MissingCredential(String),
+
+  #[error("Credential command failed -> {0}")]
+  CredentialCommand(String),
+
+  #[error("Insecure permissions on {0} -> {1}")]
+  InsecurePermissions(String, String),
 }
 
 pub type UserResult<T> = Result<T, UserError>;
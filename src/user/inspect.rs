@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use serde::{Serialize, Serializer};
+use tracing::warn;
+
+use super::args::{OutputFormat, UserInspectArgs, UserVerifyArgs};
+use super::error::{UserError, UserResult};
+use super::store::resolve_user_file;
+use super::{perms, prompt, read_user_file, Password};
+
+#[derive(Serialize)]
+pub struct InspectReport {
+  pub file: String,
+  pub format_version: u8,
+  pub encryption: &'static str,
+  /// Octal permission bits, e.g. `0600`.
+  pub mode: Option<String>,
+  pub owner_uid: Option<u32>,
+  pub permission_issues: Vec<String>,
+  pub username: String,
+  pub mac: String,
+  #[serde(
+    skip_serializing_if = "Option::is_none",
+    serialize_with = "expose_password"
+  )]
+  pub password: Option<Password>,
+}
+
+/// Writes the revealed password straight from [`Password`], so no copy of it
+/// outlives the report.
+fn expose_password<S: Serializer>(
+  password: &Option<Password>,
+  serializer: S,
+) -> Result<S::Ok, S::Error> {
+  password
+    .as_ref()
+    .map(Password::expose)
+    .serialize(serializer)
+}
+
+impl InspectReport {
+  fn print_text(&self) {
+    println!("File: {}", self.file);
+    println!("Format version: {}", self.format_version);
+    println!("Encryption: {}", self.encryption);
+    match (&self.mode, self.owner_uid) {
+      (Some(mode), Some(uid)) => {
+        println!("Permissions: {} (uid {})", mode, uid)
+      }
+      _ => println!("Permissions: unknown"),
+    }
+    println!("Username: {}", self.username);
+    println!("MAC: {}", self.mac);
+    if let Some(password) = &self.password {
+      println!("Password: {}", password.expose());
+    }
+  }
+}
+
+pub fn inspect(args: UserInspectArgs) -> UserResult<()> {
+  let file = resolve_user_file(args.file, args.name.as_deref())?;
+  let issues = perms::verify_permissions(&file, args.strict_perms)?;
+  for issue in &issues {
+    warn!("User file {}: {}", file, issue);
+  }
+
+  let reveal = args.show_password
+    && prompt::confirm("Print the stored password in clear text?")?;
+  if args.show_password && !reveal {
+    return Err(UserError::Declined);
+  }
+
+  let (format, user) = read_user_file(&file, args.identity.as_deref())?;
+  let mode_and_owner = perms::mode_and_owner(Path::new(&file))?;
+
+  let report = InspectReport {
+    file,
+    format_version: format.version(),
+    encryption: format.encryption(),
+    mode: mode_and_owner.map(|(mode, _)| format!("{:04o}", mode)),
+    owner_uid: mode_and_owner.map(|(_, uid)| uid),
+    permission_issues: issues.iter().map(ToString::to_string).collect(),
+    mac: super::User::format_mac(&user.mac),
+    password: reveal.then(|| user.password.clone()),
+    username: user.username.clone(),
+  };
+
+  match args.format {
+    OutputFormat::Text => report.print_text(),
+    OutputFormat::Json => {
+      println!("{}", serde_json::to_string_pretty(&report)?)
+    }
+  }
+  Ok(())
+}
+
+pub fn verify(args: UserVerifyArgs) -> UserResult<()> {
+  let file = resolve_user_file(args.file, args.name.as_deref())?;
+  for issue in perms::verify_permissions(&file, args.strict_perms)? {
+    warn!("User file {}: {}", file, issue);
+  }
+  let (_, user) = read_user_file(&file, args.identity.as_deref())?;
+  user.validate()?;
+  println!("User file is valid: {}", file);
+  Ok(())
+}
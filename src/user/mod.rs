@@ -1,32 +1,57 @@
+#[cfg(feature = "cli")]
 pub mod args;
 pub mod cipher;
+#[cfg(feature = "cli")]
+mod command;
 pub mod data;
 pub mod error;
+#[cfg(feature = "cli")]
+pub mod inspect;
+pub mod perms;
+#[cfg(feature = "cli")]
+pub mod prompt;
+pub mod provider;
+pub mod recipient;
+pub mod secret;
+pub mod store;
 
+#[cfg(feature = "cli")]
+pub use command::user_command_resolver;
 pub use data::User;
+pub use error::{UserError, UserResult};
+pub use secret::Password;
 
-use args::{UserArgs, UserCommand};
-use cipher::UserCipher;
-use error::UserResult;
-use std::fs::OpenOptions;
+use cipher::{UserCipher, UserFileFormat};
+use tracing::debug;
 
-pub fn user_command_resolver(args: UserArgs) -> UserResult<()> {
-  match args.command {
-    UserCommand::Create(create_args) => {
-      let fd = OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(&create_args.file)?;
-      let mac = User::transform_mac(&create_args.mac)?;
-      let user = User::new(create_args.username, create_args.password, mac);
-      UserCipher::encrypt(fd, user)?;
-      println!("User file created: {}", create_args.file);
-    }
-    UserCommand::Inspect(inspect_args) => {
-      let fd = OpenOptions::new().read(true).open(&inspect_args.file)?;
-      let user = UserCipher::decrypt(fd)?;
-      println!("Username: {}", user.username);
+/// Reads and decrypts a user file, returning its format along with the user.
+pub fn read_user_file(
+  file: &str,
+  identity: Option<&str>,
+) -> UserResult<(UserFileFormat, User)> {
+  let contents = std::fs::read(file)?;
+  let format = UserFileFormat::detect(&contents);
+  debug!(file, version = format.version(), "Reading user file");
+  let identities = match identity {
+    Some(identity) => recipient::load_identities(identity)?,
+    None => Vec::new(),
+  };
+  let user =
+    UserCipher::decrypt_with_identities(contents.as_slice(), &identities)?;
+  Ok((format, user))
+}
+
+/// Encrypts a user, to the given recipients if there are any.
+pub fn encrypt_user(user: User, recipients: &[String]) -> UserResult<Vec<u8>> {
+  let mut buffer = Vec::new();
+  if recipients.is_empty() {
+    UserCipher::encrypt(&mut buffer, user)?;
+  } else {
+    let mut parsed = Vec::new();
+    for spec in recipients {
+      parsed.extend(recipient::parse_recipients(spec)?);
     }
+    UserCipher::encrypt_to_recipients(&mut buffer, user, &parsed)?;
   }
-  Ok(())
+  Ok(buffer)
 }
@@ -0,0 +1,256 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use super::error::{UserError, UserResult};
+
+/// A reason why a user file may be readable by someone else.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PermissionIssue {
+  /// The file mode grants access to group or others.
+  GroupOrOtherAccess(u32),
+  /// The file is owned by neither the current user nor root.
+  ForeignOwner(u32),
+}
+
+impl fmt::Display for PermissionIssue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PermissionIssue::GroupOrOtherAccess(mode) => write!(
+        f,
+        "mode {:04o} is accessible by group or others, expected 0600",
+        mode
+      ),
+      PermissionIssue::ForeignOwner(uid) => {
+        write!(f, "owned by uid {}, not by the current user", uid)
+      }
+    }
+  }
+}
+
+/// Creates a new user file that only its owner can read and write.
+pub fn create_private(path: &str) -> UserResult<File> {
+  let mut options = OpenOptions::new();
+  options.write(true).create_new(true);
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.mode(0o600);
+  }
+  Ok(options.open(path)?)
+}
+
+/// Replaces `path` with `contents` atomically, through a temporary file in
+/// the same directory and a rename. An existing file keeps its mode and
+/// ownership, a new one is created with mode 0600.
+pub fn write_private_atomic(path: &str, contents: &[u8]) -> UserResult<()> {
+  let target = Path::new(path);
+  let file_name = target
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .unwrap_or_default();
+  let tmp_path =
+    target.with_file_name(format!(".{}.tmp.{}", file_name, std::process::id()));
+  let tmp = tmp_path.to_string_lossy().to_string();
+
+  let result = (|| {
+    let mut fd = create_private(&tmp)?;
+    copy_metadata(target, &tmp_path)?;
+    fd.write_all(contents)?;
+    fd.sync_all()?;
+    std::fs::rename(&tmp_path, target)?;
+    Ok(())
+  })();
+  if result.is_err() {
+    let _ = std::fs::remove_file(&tmp_path);
+  }
+  result
+}
+
+#[cfg(unix)]
+fn copy_metadata(from: &Path, to: &Path) -> UserResult<()> {
+  use std::os::unix::fs::MetadataExt;
+
+  let metadata = match std::fs::metadata(from) {
+    Ok(metadata) => metadata,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+    Err(e) => return Err(e.into()),
+  };
+  std::fs::set_permissions(to, metadata.permissions())?;
+  let current = std::fs::metadata(to)?;
+  if (current.uid(), current.gid()) != (metadata.uid(), metadata.gid()) {
+    std::os::unix::fs::chown(to, Some(metadata.uid()), Some(metadata.gid()))?;
+  }
+  Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_metadata(from: &Path, to: &Path) -> UserResult<()> {
+  match std::fs::metadata(from) {
+    Ok(metadata) => Ok(std::fs::set_permissions(to, metadata.permissions())?),
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+    Err(e) => Err(e.into()),
+  }
+}
+
+#[cfg(unix)]
+pub fn check_permissions(path: &Path) -> UserResult<Vec<PermissionIssue>> {
+  use std::os::unix::fs::MetadataExt;
+
+  let metadata = std::fs::metadata(path)?;
+  let mut issues = Vec::new();
+
+  let mode = metadata.mode() & 0o7777;
+  if mode & 0o077 != 0 {
+    issues.push(PermissionIssue::GroupOrOtherAccess(mode));
+  }
+
+  // SAFETY: geteuid has no preconditions and cannot fail
+  let euid = unsafe { libc::geteuid() };
+  if metadata.uid() != euid && metadata.uid() != 0 {
+    issues.push(PermissionIssue::ForeignOwner(metadata.uid()));
+  }
+
+  Ok(issues)
+}
+
+#[cfg(not(unix))]
+pub fn check_permissions(_path: &Path) -> UserResult<Vec<PermissionIssue>> {
+  Ok(Vec::new())
+}
+
+/// Returns the permission bits and owner uid of a file.
+#[cfg(unix)]
+pub fn mode_and_owner(path: &Path) -> UserResult<Option<(u32, u32)>> {
+  use std::os::unix::fs::MetadataExt;
+
+  let metadata = std::fs::metadata(path)?;
+  Ok(Some((metadata.mode() & 0o7777, metadata.uid())))
+}
+
+#[cfg(not(unix))]
+pub fn mode_and_owner(_path: &Path) -> UserResult<Option<(u32, u32)>> {
+  Ok(None)
+}
+
+/// Checks a user file before it is read. With `strict`, any issue is an
+/// error; otherwise the issues are returned for the caller to warn about.
+pub fn verify_permissions(
+  path: &str,
+  strict: bool,
+) -> UserResult<Vec<PermissionIssue>> {
+  let issues = check_permissions(Path::new(path))?;
+  if strict && !issues.is_empty() {
+    let reason = issues
+      .iter()
+      .map(ToString::to_string)
+      .collect::<Vec<_>>()
+      .join(", ");
+    return Err(UserError::InsecurePermissions(path.to_string(), reason));
+  }
+  Ok(issues)
+}
+
+/// Changes the owner and group of a user file, each given as a name or id.
+#[cfg(unix)]
+pub fn set_owner(
+  path: &str,
+  owner: Option<&str>,
+  group: Option<&str>,
+) -> UserResult<()> {
+  if owner.is_none() && group.is_none() {
+    return Ok(());
+  }
+  let uid = owner.map(resolve_uid).transpose()?;
+  let gid = group.map(resolve_gid).transpose()?;
+  std::os::unix::fs::chown(path, uid, gid)?;
+  Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn set_owner(
+  _path: &str,
+  owner: Option<&str>,
+  group: Option<&str>,
+) -> UserResult<()> {
+  match owner.or(group) {
+    Some(name) => Err(UserError::UnknownOwner(name.to_string())),
+    None => Ok(()),
+  }
+}
+
+#[cfg(unix)]
+fn resolve_uid(owner: &str) -> UserResult<u32> {
+  if let Ok(uid) = owner.parse() {
+    return Ok(uid);
+  }
+  let name = std::ffi::CString::new(owner)
+    .map_err(|_| UserError::UnknownOwner(owner.to_string()))?;
+  // SAFETY: name is a valid C string, the returned entry is read immediately
+  let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+  if passwd.is_null() {
+    return Err(UserError::UnknownOwner(owner.to_string()));
+  }
+  Ok(unsafe { (*passwd).pw_uid })
+}
+
+#[cfg(unix)]
+fn resolve_gid(group: &str) -> UserResult<u32> {
+  if let Ok(gid) = group.parse() {
+    return Ok(gid);
+  }
+  let name = std::ffi::CString::new(group)
+    .map_err(|_| UserError::UnknownGroup(group.to_string()))?;
+  // SAFETY: name is a valid C string, the returned entry is read immediately
+  let grp = unsafe { libc::getgrnam(name.as_ptr()) };
+  if grp.is_null() {
+    return Err(UserError::UnknownGroup(group.to_string()));
+  }
+  Ok(unsafe { (*grp).gr_gid })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use crate::testing::temp_path;
+  use std::os::unix::fs::PermissionsExt;
+
+  #[test]
+  fn test_check_permissions() {
+    let path = temp_path("perms.usr");
+    let _ = std::fs::remove_file(&path);
+
+    create_private(path.to_str().unwrap()).unwrap();
+    assert_eq!(check_permissions(&path).unwrap(), vec![]);
+
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644))
+      .unwrap();
+    assert_eq!(
+      check_permissions(&path).unwrap(),
+      vec![PermissionIssue::GroupOrOtherAccess(0o644)]
+    );
+
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn test_write_private_atomic() {
+    let path = temp_path("atomic.usr");
+    let file = path.to_str().unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    write_private_atomic(file, b"first").unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"first");
+    assert_eq!(check_permissions(&path).unwrap(), vec![]);
+
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640))
+      .unwrap();
+    write_private_atomic(file, b"second").unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"second");
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+
+    std::fs::remove_file(&path).unwrap();
+  }
+}
@@ -0,0 +1,45 @@
+use std::io::BufRead;
+
+use zeroize::Zeroizing;
+
+use super::error::{UserError, UserResult};
+use super::secret::Password;
+
+/// Asks for a new password on the terminal with echo disabled, twice.
+pub fn prompt_new_password() -> UserResult<Password> {
+  let password = Zeroizing::new(
+    rpassword::prompt_password("Password: ").map_err(UserError::Prompt)?,
+  );
+  let confirm = Zeroizing::new(
+    rpassword::prompt_password("Confirm password: ")
+      .map_err(UserError::Prompt)?,
+  );
+  if password != confirm {
+    return Err(UserError::PasswordMismatch);
+  }
+  Ok(password.as_str().into())
+}
+
+/// Reads a password from the first line of standard input.
+pub fn read_password_stdin() -> UserResult<Password> {
+  let mut line = Zeroizing::new(String::new());
+  std::io::stdin().lock().read_line(&mut line)?;
+  Ok(line.trim_end_matches(['\r', '\n']).into())
+}
+
+/// Asks a yes/no question on the terminal, failing with
+/// [`UserError::NotConfirmed`] when there is none.
+pub fn confirm(question: &str) -> UserResult<bool> {
+  eprint!("{} [y/N] ", question);
+  let mut answer = String::new();
+  #[cfg(unix)]
+  let read = std::fs::File::open("/dev/tty")
+    .and_then(|tty| std::io::BufReader::new(tty).read_line(&mut answer));
+  #[cfg(not(unix))]
+  let read = std::io::stdin().lock().read_line(&mut answer);
+  if read.is_err() {
+    eprintln!();
+    return Err(UserError::NotConfirmed);
+  }
+  Ok(matches!(answer.trim(), "y" | "Y" | "yes" | "YES"))
+}
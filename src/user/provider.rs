@@ -0,0 +1,369 @@
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use tracing::warn;
+use zeroize::Zeroizing;
+
+use super::cipher::UserCipher;
+use super::data::User;
+use super::error::{UserError, UserResult};
+use super::perms;
+use super::recipient::load_identities;
+use super::store::resolve_user_file;
+
+/// Options shared by the providers that read user files.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderOptions {
+  /// Refuse user files readable by group or others.
+  pub strict_perms: bool,
+  /// Identity file for user files encrypted to recipients.
+  pub identity: Option<String>,
+}
+
+/// A source of user credentials.
+///
+/// Providers are asked for credentials again before every authentication
+/// attempt, so changes to the underlying source are picked up on retry.
+pub trait CredentialProvider {
+  fn load(&self) -> UserResult<User>;
+
+  /// A short human readable description of the source, without secrets.
+  fn describe(&self) -> String;
+}
+
+/// Builds a provider from a credential URI.
+///
+/// Supported forms are `file:<path>`, `store:[name]`, `systemd:[name]`,
+/// `env:[prefix]`, `stdin:` and `cmd:<command>`. Anything else is treated as
+/// a file path.
+pub fn provider_from_uri(
+  uri: &str,
+  options: &ProviderOptions,
+) -> UserResult<Box<dyn CredentialProvider>> {
+  let (scheme, rest) = uri.split_once(':').unwrap_or(("", uri));
+  let provider: Box<dyn CredentialProvider> = match scheme {
+    "file" => Box::new(FileProvider::new(rest, options)),
+    "store" => Box::new(StoreProvider::new(rest, options)),
+    "systemd" => Box::new(SystemdProvider::new(rest, options)),
+    "env" => Box::new(EnvProvider::new(rest)),
+    "stdin" => Box::new(StdinProvider::default()),
+    "cmd" => Box::new(CommandProvider::new(rest)),
+    _ => Box::new(FileProvider::new(uri, options)),
+  };
+  Ok(provider)
+}
+
+/// Reads an encrypted user file created by `user create`.
+pub struct FileProvider {
+  path: String,
+  options: ProviderOptions,
+}
+
+impl FileProvider {
+  pub fn new(path: &str, options: &ProviderOptions) -> Self {
+    Self {
+      path: path.to_string(),
+      options: options.clone(),
+    }
+  }
+}
+
+impl CredentialProvider for FileProvider {
+  fn load(&self) -> UserResult<User> {
+    for issue in
+      perms::verify_permissions(&self.path, self.options.strict_perms)?
+    {
+      warn!("User file {}: {}", self.path, issue);
+    }
+    let identities = match &self.options.identity {
+      Some(identity) => load_identities(identity)?,
+      None => Vec::new(),
+    };
+    let fd = OpenOptions::new().read(true).open(&self.path)?;
+    // decryption returns the file as stored, the other providers validate too
+    let user = UserCipher::decrypt_with_identities(fd, &identities)?;
+    user.validate()?;
+    Ok(user)
+  }
+
+  fn describe(&self) -> String {
+    format!("file {}", self.path)
+  }
+}
+
+/// Reads a named entry of the user store, or its only entry when no name
+/// is given.
+pub struct StoreProvider {
+  name: Option<String>,
+  options: ProviderOptions,
+}
+
+impl StoreProvider {
+  pub fn new(name: &str, options: &ProviderOptions) -> Self {
+    Self {
+      name: (!name.is_empty()).then(|| name.to_string()),
+      options: options.clone(),
+    }
+  }
+}
+
+impl CredentialProvider for StoreProvider {
+  fn load(&self) -> UserResult<User> {
+    let file = resolve_user_file(None, self.name.as_deref())?;
+    FileProvider::new(&file, &self.options).load()
+  }
+
+  fn describe(&self) -> String {
+    match &self.name {
+      Some(name) => format!("store user {}", name),
+      None => "default store user".to_string(),
+    }
+  }
+}
+
+/// Reads an encrypted user file passed in as a systemd credential
+/// (`LoadCredential=` or `LoadCredentialEncrypted=`).
+pub struct SystemdProvider {
+  name: String,
+  options: ProviderOptions,
+}
+
+impl SystemdProvider {
+  pub const DEFAULT_NAME: &'static str = "cygnus.usr";
+
+  pub fn new(name: &str, options: &ProviderOptions) -> Self {
+    let name = match name {
+      "" => Self::DEFAULT_NAME,
+      name => name,
+    };
+    Self {
+      name: name.to_string(),
+      options: options.clone(),
+    }
+  }
+}
+
+impl CredentialProvider for SystemdProvider {
+  fn load(&self) -> UserResult<User> {
+    let dir = std::env::var("CREDENTIALS_DIRECTORY").map_err(|_| {
+      UserError::MissingCredential("$CREDENTIALS_DIRECTORY".to_string())
+    })?;
+    let path = std::path::Path::new(&dir).join(&self.name);
+    FileProvider::new(&path.to_string_lossy(), &self.options).load()
+  }
+
+  fn describe(&self) -> String {
+    format!("systemd credential {}", self.name)
+  }
+}
+
+/// Reads `<prefix>USERNAME`, `<prefix>PASSWORD` and `<prefix>MAC` from the
+/// environment.
+pub struct EnvProvider {
+  prefix: String,
+}
+
+impl EnvProvider {
+  pub const DEFAULT_PREFIX: &'static str = "CYGNUS_";
+
+  pub fn new(prefix: &str) -> Self {
+    let prefix = match prefix {
+      "" => Self::DEFAULT_PREFIX,
+      prefix => prefix,
+    };
+    Self {
+      prefix: prefix.to_string(),
+    }
+  }
+
+  fn var(&self, name: &str) -> UserResult<String> {
+    let key = format!("{}{}", self.prefix, name);
+    std::env::var(&key).map_err(|_| UserError::MissingCredential(key))
+  }
+}
+
+impl CredentialProvider for EnvProvider {
+  fn load(&self) -> UserResult<User> {
+    let username = self.var("USERNAME")?;
+    let password = Zeroizing::new(self.var("PASSWORD")?);
+    let mac = User::transform_mac(&self.var("MAC")?)?;
+    User::new(username, password.as_str().into(), mac)
+  }
+
+  fn describe(&self) -> String {
+    format!("environment {}*", self.prefix)
+  }
+}
+
+/// Reads credentials in text form from standard input.
+///
+/// Standard input can only be consumed once, so the text is kept for
+/// later attempts.
+#[derive(Default)]
+pub struct StdinProvider {
+  text: OnceLock<Zeroizing<String>>,
+}
+
+impl CredentialProvider for StdinProvider {
+  fn load(&self) -> UserResult<User> {
+    if self.text.get().is_none() {
+      let mut text = Zeroizing::new(String::new());
+      std::io::stdin().read_to_string(&mut text)?;
+      let _ = self.text.set(text);
+    }
+    parse_credentials(self.text.get().map(|t| t.as_str()).unwrap_or(""))
+  }
+
+  fn describe(&self) -> String {
+    "stdin".to_string()
+  }
+}
+
+/// Runs a shell command (for example `pass show jlu`) and reads credentials
+/// in text form from its output.
+pub struct CommandProvider {
+  command: String,
+}
+
+impl CommandProvider {
+  pub fn new(command: &str) -> Self {
+    Self {
+      command: command.to_string(),
+    }
+  }
+}
+
+impl CredentialProvider for CommandProvider {
+  fn load(&self) -> UserResult<User> {
+    let output = Command::new("sh")
+      .arg("-c")
+      .arg(&self.command)
+      .stdin(Stdio::null())
+      .stderr(Stdio::inherit())
+      .output()?;
+    let stdout = Zeroizing::new(output.stdout);
+    if !output.status.success() {
+      return Err(UserError::CredentialCommand(output.status.to_string()));
+    }
+    parse_credentials(std::str::from_utf8(&stdout)?)
+  }
+
+  fn describe(&self) -> String {
+    format!("command `{}`", self.command)
+  }
+}
+
+/// Parses credentials in text form.
+///
+/// Each line is a `key=value` or `key: value` pair with the keys `username`,
+/// `password` and `mac`. Following the `pass` convention, a first line that
+/// is not such a pair is taken as the password. Passwords are kept as
+/// written, only the space after the separator is dropped.
+pub fn parse_credentials(text: &str) -> UserResult<User> {
+  let mut username = None;
+  let mut password = None;
+  let mut mac = None;
+
+  for (i, line) in text.lines().enumerate() {
+    let field = line
+      .split_once(['=', ':'])
+      .map(|(key, value)| (key.trim().to_ascii_lowercase(), value));
+    match field {
+      Some((key, value)) if key == "username" || key == "user" => {
+        username = Some(value.trim().to_string())
+      }
+      Some((key, value)) if key == "password" => {
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        password = Some(Zeroizing::new(value.to_string()))
+      }
+      Some((key, value)) if key == "mac" => {
+        mac = Some(value.trim().to_string())
+      }
+      _ if i == 0 && !line.is_empty() => {
+        password = Some(Zeroizing::new(line.to_string()))
+      }
+      _ => {}
+    }
+  }
+
+  let username = username
+    .ok_or_else(|| UserError::MissingCredential("username".to_string()))?;
+  let password = password
+    .ok_or_else(|| UserError::MissingCredential("password".to_string()))?;
+  let mac =
+    mac.ok_or_else(|| UserError::MissingCredential("mac".to_string()))?;
+
+  User::new(
+    username,
+    password.as_str().into(),
+    User::transform_mac(&mac)?,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_credentials() {
+    let expected =
+      User::new("user".into(), "p@ss:w=rd".into(), [0, 1, 2, 3, 4, 5]).unwrap();
+
+    let text = "username=user\npassword=p@ss:w=rd\nmac=00:01:02:03:04:05\n";
+    assert_eq!(parse_credentials(text).unwrap(), expected);
+
+    let text = "p@ss:w=rd\nuser: user\nmac: 00:01:02:03:04:05\n";
+    assert_eq!(parse_credentials(text).unwrap(), expected);
+
+    // spaces around a password are part of it
+    let text =
+      "username: user\r\npassword:  pass \r\nmac=00:01:02:03:04:05\r\n";
+    assert_eq!(parse_credentials(text).unwrap().password.expose(), " pass ");
+
+    assert!(matches!(
+      parse_credentials("username=user\nmac=00:01:02:03:04:05"),
+      Err(UserError::MissingCredential(_))
+    ));
+  }
+
+  #[test]
+  fn test_provider_from_uri() {
+    let cases = [
+      ("cygnus.usr", "file cygnus.usr"),
+      ("file:/etc/cygnus.usr", "file /etc/cygnus.usr"),
+      ("store:lab", "store user lab"),
+      ("store:", "default store user"),
+      ("systemd:", "systemd credential cygnus.usr"),
+      ("env:", "environment CYGNUS_*"),
+      ("env:JLU_", "environment JLU_*"),
+      ("stdin:", "stdin"),
+      ("cmd:pass show jlu", "command `pass show jlu`"),
+    ];
+    for (uri, description) in cases {
+      assert_eq!(
+        provider_from_uri(uri, &ProviderOptions::default())
+          .unwrap()
+          .describe(),
+        description
+      );
+    }
+  }
+
+  #[test]
+  fn test_command_provider() {
+    let provider = CommandProvider::new(
+      "printf 'secret\\nusername: user\\nmac: 00:01:02:03:04:05\\n'",
+    );
+    let user = provider.load().unwrap();
+    assert_eq!(user.username, "user");
+    assert_eq!(user.password.expose(), "secret");
+
+    let provider = CommandProvider::new("exit 3");
+    assert!(matches!(
+      provider.load(),
+      Err(UserError::CredentialCommand(_))
+    ));
+  }
+}
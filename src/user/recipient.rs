@@ -0,0 +1,101 @@
+use std::io::BufReader;
+
+use super::error::{UserError, UserResult};
+
+/// Parses recipients for `user create --recipient`.
+///
+/// `spec` is an age public key (`age1...`), an SSH public key
+/// (`ssh-ed25519 ...` or `ssh-rsa ...`), or a file containing such keys one
+/// per line, like `~/.ssh/id_ed25519.pub`.
+pub fn parse_recipients(
+  spec: &str,
+) -> UserResult<Vec<Box<dyn age::Recipient + Send>>> {
+  let spec = spec.trim();
+  if spec.starts_with("age1") || spec.starts_with("ssh-") {
+    return Ok(vec![parse_recipient(spec)?]);
+  }
+  let contents = std::fs::read_to_string(spec)
+    .map_err(|_| UserError::InvalidRecipient(spec.to_string()))?;
+  let recipients = contents
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    .map(parse_recipient)
+    .collect::<UserResult<Vec<_>>>()?;
+  if recipients.is_empty() {
+    return Err(UserError::InvalidRecipient(spec.to_string()));
+  }
+  Ok(recipients)
+}
+
+fn parse_recipient(key: &str) -> UserResult<Box<dyn age::Recipient + Send>> {
+  if let Ok(recipient) = key.parse::<age::x25519::Recipient>() {
+    return Ok(Box::new(recipient));
+  }
+  if let Ok(recipient) = key.parse::<age::ssh::Recipient>() {
+    return Ok(Box::new(recipient));
+  }
+  // only show the key type, the rest is not useful in an error message
+  let kind = key.split_whitespace().next().unwrap_or_default();
+  Err(UserError::InvalidRecipient(kind.to_string()))
+}
+
+/// Loads the identities of `auth --identity`, from either an age identity
+/// file or an unencrypted SSH private key.
+pub fn load_identities(path: &str) -> UserResult<Vec<Box<dyn age::Identity>>> {
+  let contents = std::fs::read(path)?;
+  if contents.starts_with(b"-----BEGIN") {
+    let identity = age::ssh::Identity::from_buffer(
+      BufReader::new(contents.as_slice()),
+      Some(path.to_string()),
+    )?;
+    return match identity {
+      age::ssh::Identity::Unencrypted(_) => Ok(vec![Box::new(identity)]),
+      age::ssh::Identity::Encrypted(_) => Err(UserError::Identity(format!(
+        "{} is passphrase protected, which is not supported",
+        path
+      ))),
+      age::ssh::Identity::Unsupported(_) => Err(UserError::Identity(format!(
+        "{} has an unsupported key type",
+        path
+      ))),
+    };
+  }
+  let identities =
+    age::IdentityFile::from_buffer(contents.as_slice())?.into_identities()?;
+  if identities.is_empty() {
+    return Err(UserError::Identity(format!("{} has no identities", path)));
+  }
+  Ok(identities)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::temp_path;
+  use age::secrecy::ExposeSecret;
+
+  #[test]
+  fn test_parse_recipients() {
+    let identity = age::x25519::Identity::generate();
+    let public = identity.to_public().to_string();
+    assert_eq!(parse_recipients(&public).unwrap().len(), 1);
+
+    let ssh = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHsKLqeplhpW+uObz5dvMgjz1OxfM/XXUB+VHtZ6isGN alice@rust";
+    assert_eq!(parse_recipients(ssh).unwrap().len(), 1);
+
+    assert!(matches!(
+      parse_recipients("ssh-dss AAAA"),
+      Err(UserError::InvalidRecipient(_))
+    ));
+
+    let path = temp_path("identity.txt");
+    std::fs::write(
+      &path,
+      format!("# test key\n{}\n", identity.to_string().expose_secret()),
+    )
+    .unwrap();
+    assert_eq!(load_identities(path.to_str().unwrap()).unwrap().len(), 1);
+    std::fs::remove_file(&path).unwrap();
+  }
+}
@@ -0,0 +1,270 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use zeroize::Zeroizing;
+
+#[cfg(unix)]
+/// Whether new secrets are locked into RAM, see [`lock_memory`].
+static LOCK_SECRETS: AtomicBool = AtomicBool::new(false);
+
+/// A password that is wiped from memory on drop and never printed.
+pub struct Password {
+  // dropped before `_pages`, so the pages are only unlocked once wiped
+  password: Zeroizing<String>,
+  _pages: Option<LockedPages>,
+}
+
+impl Password {
+  pub fn new(password: String) -> Self {
+    let pages = LockedPages::new(password.as_ptr(), password.capacity());
+    Self {
+      password: Zeroizing::new(password),
+      _pages: pages,
+    }
+  }
+
+  pub fn expose(&self) -> &str {
+    &self.password
+  }
+}
+
+impl Clone for Password {
+  fn clone(&self) -> Self {
+    Self::new(self.password.to_string())
+  }
+}
+
+impl Default for Password {
+  fn default() -> Self {
+    Self::new(String::new())
+  }
+}
+
+impl PartialEq for Password {
+  fn eq(&self, other: &Self) -> bool {
+    self.password == other.password
+  }
+}
+
+impl Eq for Password {}
+
+impl From<String> for Password {
+  fn from(password: String) -> Self {
+    Self::new(password)
+  }
+}
+
+impl From<&str> for Password {
+  fn from(password: &str) -> Self {
+    Self::new(password.to_string())
+  }
+}
+
+impl Deref for Password {
+  type Target = str;
+
+  fn deref(&self) -> &str {
+    self.expose()
+  }
+}
+
+impl fmt::Debug for Password {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("Password(<redacted>)")
+  }
+}
+
+/// A zeroed buffer for packets derived from the password, such as the login
+/// packet. Like [`Password`] it is wiped on drop and locked into RAM.
+///
+/// The buffer must not grow beyond its initial length, or the new
+/// allocation is not locked.
+pub struct SecretBuf {
+  buf: Zeroizing<Vec<u8>>,
+  _pages: Option<LockedPages>,
+}
+
+impl SecretBuf {
+  pub fn new(len: usize) -> Self {
+    let buf = vec![0; len];
+    let pages = LockedPages::new(buf.as_ptr(), buf.capacity());
+    Self {
+      buf: Zeroizing::new(buf),
+      _pages: pages,
+    }
+  }
+}
+
+impl Deref for SecretBuf {
+  type Target = Vec<u8>;
+
+  fn deref(&self) -> &Vec<u8> {
+    &self.buf
+  }
+}
+
+impl DerefMut for SecretBuf {
+  fn deref_mut(&mut self) -> &mut Vec<u8> {
+    &mut self.buf
+  }
+}
+
+/// Locks the pages holding passwords and login packets created from now on
+/// into RAM, so secrets held by a long-running daemon are never written to
+/// swap. Fails if a probe page cannot be locked, such as without the
+/// permission; later failures to lock a page are logged when the secret is
+/// created.
+#[cfg(unix)]
+pub fn lock_memory() -> std::io::Result<()> {
+  pages::probe()?;
+  LOCK_SECRETS.store(true, Ordering::Relaxed);
+  Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn lock_memory() -> std::io::Result<()> {
+  Err(std::io::Error::new(
+    std::io::ErrorKind::Unsupported,
+    "memory locking is not supported on this platform",
+  ))
+}
+
+/// The pages of one heap buffer, locked while this is alive.
+///
+/// Locks do not stack, so pages shared by several secrets are counted and
+/// only unlocked when the last of them is dropped.
+struct LockedPages {
+  first: usize,
+  last: usize,
+}
+
+#[cfg(unix)]
+mod pages {
+  use std::collections::BTreeMap;
+  use std::sync::{Mutex, OnceLock, PoisonError};
+
+  use tracing::warn;
+
+  /// Number of live secrets on each locked page.
+  static LOCKED: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+  pub fn size() -> usize {
+    static SIZE: OnceLock<usize> = OnceLock::new();
+    *SIZE.get_or_init(|| {
+      // SAFETY: sysconf only reads a system setting
+      let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+      usize::try_from(size).unwrap_or(4096)
+    })
+  }
+
+  pub fn lock(first: usize, last: usize) {
+    let mut locked = LOCKED.lock().unwrap_or_else(PoisonError::into_inner);
+    for page in first..=last {
+      let count = locked.entry(page).or_insert(0);
+      if *count == 0 {
+        // SAFETY: mlock only changes whether the page stays resident, it
+        // neither reads nor writes the memory
+        let ret = unsafe {
+          libc::mlock((page * size()) as *const libc::c_void, size())
+        };
+        if ret != 0 {
+          warn!("Failed to lock memory: {}", std::io::Error::last_os_error());
+        }
+      }
+      *count += 1;
+    }
+  }
+
+  /// Locks and unlocks a page of its own, to find out whether locking works.
+  pub fn probe() -> std::io::Result<()> {
+    let buf = vec![0u8; 2 * size()];
+    let page = (buf.as_ptr() as usize).div_ceil(size()) * size();
+    // SAFETY: see `lock`, the page lies within `buf` so no secret shares it
+    let ret = unsafe { libc::mlock(page as *const libc::c_void, size()) };
+    if ret != 0 {
+      return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: see `lock`
+    unsafe { libc::munlock(page as *const libc::c_void, size()) };
+    Ok(())
+  }
+
+  pub fn unlock(first: usize, last: usize) {
+    let mut locked = LOCKED.lock().unwrap_or_else(PoisonError::into_inner);
+    for page in first..=last {
+      let Some(count) = locked.get_mut(&page) else {
+        continue;
+      };
+      *count -= 1;
+      if *count == 0 {
+        locked.remove(&page);
+        // SAFETY: see `lock`
+        unsafe {
+          libc::munlock((page * size()) as *const libc::c_void, size())
+        };
+      }
+    }
+  }
+
+  #[cfg(test)]
+  pub fn count(page: usize) -> usize {
+    let locked = LOCKED.lock().unwrap_or_else(PoisonError::into_inner);
+    locked.get(&page).copied().unwrap_or(0)
+  }
+}
+
+impl LockedPages {
+  /// Locks the `len` bytes at `ptr` if [`lock_memory`] was called.
+  #[cfg(unix)]
+  fn new(ptr: *const u8, len: usize) -> Option<Self> {
+    if len == 0 || !LOCK_SECRETS.load(Ordering::Relaxed) {
+      return None;
+    }
+    let start = ptr as usize;
+    let pages = Self {
+      first: start / pages::size(),
+      last: (start + len - 1) / pages::size(),
+    };
+    pages::lock(pages.first, pages.last);
+    Some(pages)
+  }
+
+  #[cfg(not(unix))]
+  fn new(_ptr: *const u8, _len: usize) -> Option<Self> {
+    None
+  }
+}
+
+impl Drop for LockedPages {
+  fn drop(&mut self) {
+    #[cfg(unix)]
+    pages::unlock(self.first, self.last);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_debug_is_redacted() {
+    let password = Password::from("hunter2");
+    assert_eq!(format!("{:?}", password), "Password(<redacted>)");
+    assert_eq!(password.expose(), "hunter2");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_shared_pages_stay_locked() {
+    lock_memory().unwrap();
+    let first = Password::from("hunter2");
+    let page = first.expose().as_ptr() as usize / pages::size();
+    assert!(pages::count(page) >= 1);
+
+    // clones of a short password usually share its page
+    drop(first.clone());
+    assert!(pages::count(page) >= 1);
+  }
+}
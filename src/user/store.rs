@@ -0,0 +1,187 @@
+use std::path::{Path, PathBuf};
+
+use super::error::{UserError, UserResult};
+
+/// A directory of named user files, `<dir>/<name>.usr`.
+///
+/// The directory is `$CYGNUS_STORE` if set, `/etc/cygnus/users` for root
+/// (system daemons) and `$XDG_CONFIG_HOME/cygnus/users` otherwise.
+pub struct UserStore {
+  dir: PathBuf,
+}
+
+impl UserStore {
+  pub const EXTENSION: &'static str = "usr";
+  pub const SYSTEM_DIR: &'static str = "/etc/cygnus/users";
+
+  pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+    Self { dir: dir.into() }
+  }
+
+  pub fn open_default() -> UserResult<Self> {
+    if let Some(dir) = std::env::var_os("CYGNUS_STORE") {
+      return Ok(Self::new(dir));
+    }
+    if is_root() {
+      return Ok(Self::new(Self::SYSTEM_DIR));
+    }
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+      .filter(|dir| !dir.is_empty())
+      .map(PathBuf::from)
+      .or_else(|| {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+      })
+      .ok_or(UserError::NoStoreDir)?;
+    Ok(Self::new(config.join("cygnus").join("users")))
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  pub fn path(&self, name: &str) -> UserResult<PathBuf> {
+    let valid = !name.is_empty()
+      && !name.starts_with('.')
+      && name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+      return Err(UserError::InvalidName(name.to_string()));
+    }
+    Ok(self.dir.join(format!("{}.{}", name, Self::EXTENSION)))
+  }
+
+  /// Returns the path of an existing entry.
+  pub fn existing_path(&self, name: &str) -> UserResult<PathBuf> {
+    let path = self.path(name)?;
+    if !path.is_file() {
+      return Err(UserError::UnknownUser(name.to_string()));
+    }
+    Ok(path)
+  }
+
+  /// Lists entry names in sorted order. A missing store is empty.
+  pub fn list(&self) -> UserResult<Vec<String>> {
+    let entries = match std::fs::read_dir(&self.dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+        return Ok(Vec::new())
+      }
+      Err(e) => return Err(e.into()),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+      let path = entry?.path();
+      if path.extension().is_some_and(|ext| ext == Self::EXTENSION)
+        && path.is_file()
+      {
+        if let Some(name) = path.file_stem() {
+          names.push(name.to_string_lossy().to_string());
+        }
+      }
+    }
+    names.sort();
+    Ok(names)
+  }
+
+  /// The entry used when no name is given, which is only defined when the
+  /// store holds exactly one user.
+  pub fn default_name(&self) -> UserResult<String> {
+    let mut names = self.list()?;
+    match names.len() {
+      1 => Ok(names.remove(0)),
+      count => Err(UserError::NoDefaultUser(
+        count,
+        self.dir.display().to_string(),
+      )),
+    }
+  }
+
+  /// Creates the store directory, accessible only by its owner.
+  pub fn ensure_dir(&self) -> UserResult<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::DirBuilderExt;
+      builder.mode(0o700);
+    }
+    Ok(builder.create(&self.dir)?)
+  }
+
+  pub fn remove(&self, name: &str) -> UserResult<()> {
+    Ok(std::fs::remove_file(self.existing_path(name)?)?)
+  }
+}
+
+/// Resolves the user file selected by `--file` or `--name`, falling back to
+/// the default store entry.
+pub fn resolve_user_file(
+  file: Option<String>,
+  name: Option<&str>,
+) -> UserResult<String> {
+  if let Some(file) = file {
+    return Ok(file);
+  }
+  let store = UserStore::open_default()?;
+  let path = match name {
+    Some(name) => store.existing_path(name)?,
+    None => store.existing_path(&store.default_name()?)?,
+  };
+  Ok(path.to_string_lossy().to_string())
+}
+
+#[cfg(unix)]
+pub(crate) fn is_root() -> bool {
+  // SAFETY: geteuid has no preconditions and cannot fail
+  unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_root() -> bool {
+  false
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::temp_path;
+
+  #[test]
+  fn test_store() {
+    let dir = temp_path("store");
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = UserStore::new(&dir);
+
+    assert_eq!(store.list().unwrap(), Vec::<String>::new());
+    assert!(matches!(
+      store.default_name(),
+      Err(UserError::NoDefaultUser(0, _))
+    ));
+
+    store.ensure_dir().unwrap();
+    std::fs::write(store.path("lab").unwrap(), b"").unwrap();
+    std::fs::write(dir.join("notes.txt"), b"").unwrap();
+    assert_eq!(store.default_name().unwrap(), "lab");
+
+    std::fs::write(store.path("guest").unwrap(), b"").unwrap();
+    assert_eq!(store.list().unwrap(), vec!["guest", "lab"]);
+    assert!(matches!(
+      store.default_name(),
+      Err(UserError::NoDefaultUser(2, _))
+    ));
+
+    store.remove("guest").unwrap();
+    assert!(matches!(
+      store.remove("guest"),
+      Err(UserError::UnknownUser(_))
+    ));
+    assert!(matches!(store.path("../x"), Err(UserError::InvalidName(_))));
+    assert!(matches!(
+      store.path(".hidden"),
+      Err(UserError::InvalidName(_))
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}